@@ -0,0 +1,555 @@
+use nalgebra::{Matrix4, Point3, Rotation3, Unit, UnitQuaternion, Vector3, Vector4};
+use serde::{Deserialize, Serialize};
+
+use crate::entity::{validate_axis, Entity, Transform};
+use crate::geometry::{Color, Model};
+use crate::interpolation::{self, Easing, PlaybackMode};
+use crate::scene_loader::SceneError;
+
+#[derive(Copy, Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TrackInterpolation {
+    // Holds each keyframe's value until the next keyframe
+    Step,
+    #[default]
+    Linear,
+    // Catmull-Rom spline through the keyframes
+    Cubic,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Keyframe<T> {
+    pub time: f64,
+    pub value: T,
+}
+
+/*
+    Keyframed values of one property over time. Sampling before the first keyframe or
+    after the last holds the value of that keyframe.
+*/
+#[derive(Clone, Debug, PartialEq)]
+pub struct Track<T> {
+    keyframes: Vec<Keyframe<T>>,
+}
+
+impl<T: Copy> Track<T> {
+    pub fn new(keyframes: Vec<Keyframe<T>>) -> Self {
+        assert!(!keyframes.is_empty(), "Animation track requires a keyframe");
+
+        let mut keyframes = keyframes;
+        keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+        Track { keyframes }
+    }
+
+    pub fn get_duration(&self) -> f64 {
+        self.keyframes
+            .last()
+            .map(|keyframe| keyframe.time)
+            .unwrap_or(0.0)
+    }
+
+    // The four keyframe values around the time, and the fraction between the middle two
+    fn get_segment(&self, time: f64, easing: Easing) -> ([T; 4], f64) {
+        let times: Vec<f64> = self
+            .keyframes
+            .iter()
+            .map(|keyframe| keyframe.time)
+            .collect();
+        let (index, t) = interpolation::find_segment(&times, time);
+
+        let last = self.keyframes.len() - 1;
+        let values = [
+            self.keyframes[index.saturating_sub(1)].value,
+            self.keyframes[index].value,
+            self.keyframes[(index + 1).min(last)].value,
+            self.keyframes[(index + 2).min(last)].value,
+        ];
+        (values, easing.apply(t))
+    }
+}
+
+impl Track<Vector3<f64>> {
+    pub fn sample(
+        &self,
+        time: f64,
+        interpolation: TrackInterpolation,
+        easing: Easing,
+    ) -> Vector3<f64> {
+        let ([v0, v1, v2, v3], t) = self.get_segment(time, easing);
+        match interpolation {
+            TrackInterpolation::Step if t < 1.0 => v1,
+            TrackInterpolation::Step => v2,
+            TrackInterpolation::Linear => interpolation::lerp(v1, v2, t),
+            TrackInterpolation::Cubic => interpolation::catmull_rom(v0, v1, v2, v3, t),
+        }
+    }
+}
+
+impl Track<UnitQuaternion<f64>> {
+    pub fn sample(
+        &self,
+        time: f64,
+        interpolation: TrackInterpolation,
+        easing: Easing,
+    ) -> UnitQuaternion<f64> {
+        let ([q0, q1, q2, q3], t) = self.get_segment(time, easing);
+        match interpolation {
+            TrackInterpolation::Step if t < 1.0 => q1,
+            TrackInterpolation::Step => q2,
+            TrackInterpolation::Linear => q1.slerp(&q2, t),
+            TrackInterpolation::Cubic => {
+                // The spline is run on the quaternion components, flipped into the same
+                // hemisphere as q1 so it takes the short way around, then renormalized
+                let align = |q: UnitQuaternion<f64>| -> Vector4<f64> {
+                    if q.coords.dot(&q1.coords) < 0.0 {
+                        -q.coords
+                    } else {
+                        q.coords
+                    }
+                };
+                let coords =
+                    interpolation::catmull_rom(align(q0), q1.coords, align(q2), align(q3), t);
+                UnitQuaternion::new_normalize(nalgebra::Quaternion::from(coords))
+            }
+        }
+    }
+}
+
+/*
+    Translation, rotation and scale tracks played back together. The animated values are
+    relative to the entity's own transform, so a translation of zero leaves it at its origin.
+*/
+#[derive(Clone, Debug, PartialEq)]
+pub struct Animation {
+    translation: Option<Track<Vector3<f64>>>,
+    rotation: Option<Track<UnitQuaternion<f64>>>,
+    scale: Option<Track<Vector3<f64>>>,
+    interpolation: TrackInterpolation,
+    easing: Easing,
+    playback_mode: PlaybackMode,
+    time: f64,
+}
+
+impl Animation {
+    pub fn new(
+        translation: Option<Track<Vector3<f64>>>,
+        rotation: Option<Track<UnitQuaternion<f64>>>,
+        scale: Option<Track<Vector3<f64>>>,
+        interpolation: TrackInterpolation,
+        easing: Easing,
+        playback_mode: PlaybackMode,
+    ) -> Self {
+        Animation {
+            translation,
+            rotation,
+            scale,
+            interpolation,
+            easing,
+            playback_mode,
+            time: 0.0,
+        }
+    }
+
+    // Parses the animation object of an entity in the scene file, with errors located
+    // relative to the animation object
+    pub fn from_json(json: &serde_json::Value) -> Result<Self, SceneError> {
+        let animation = JsonAnimation::deserialize(json)
+            .map_err(|error| SceneError::from_serde("", &error.to_string()))?;
+        if animation.translation.is_empty()
+            && animation.rotation.is_empty()
+            && animation.scale.is_empty()
+        {
+            return Err(SceneError::new(
+                "",
+                "Animation requires a translation, rotation or scale track",
+            ));
+        }
+
+        let vector_track = |track_name: &str, keyframes: &[JsonVectorKeyframe]| {
+            let keyframes = keyframes
+                .iter()
+                .enumerate()
+                .map(|(index, keyframe)| {
+                    validate_time(keyframe.time).map_err(|error| {
+                        SceneError::new(&keyframe_path(track_name, index), &error)
+                    })?;
+                    Ok(Keyframe {
+                        time: keyframe.time,
+                        value: Vector3::from(keyframe.value),
+                    })
+                })
+                .collect::<Result<Vec<_>, SceneError>>()?;
+            Ok::<_, SceneError>((!keyframes.is_empty()).then(|| Track::new(keyframes)))
+        };
+
+        let rotation_keyframes = animation
+            .rotation
+            .iter()
+            .enumerate()
+            .map(|(index, keyframe)| {
+                validate_time(keyframe.time)
+                    .and_then(|_| validate_axis("axis", &keyframe.axis))
+                    .map_err(|error| SceneError::new(&keyframe_path("rotation", index), &error))?;
+                Ok(Keyframe {
+                    time: keyframe.time,
+                    value: UnitQuaternion::from_axis_angle(
+                        &Unit::new_normalize(Vector3::from(keyframe.axis)),
+                        keyframe.angle,
+                    ),
+                })
+            })
+            .collect::<Result<Vec<_>, SceneError>>()?;
+        let rotation = (!rotation_keyframes.is_empty()).then(|| Track::new(rotation_keyframes));
+
+        Ok(Animation::new(
+            vector_track("translation", &animation.translation)?,
+            rotation,
+            vector_track("scale", &animation.scale)?,
+            animation.interpolation,
+            animation.easing,
+            animation.playback,
+        ))
+    }
+
+    // The animation object of an entity in the scene file, with rotations as axis and angle
+    pub fn to_json(&self) -> serde_json::Value {
+        let vector_keyframes = |track: &Option<Track<Vector3<f64>>>| {
+            track
+                .iter()
+                .flat_map(|track| &track.keyframes)
+                .map(|keyframe| JsonVectorKeyframe {
+                    time: keyframe.time,
+                    value: keyframe.value.into(),
+                })
+                .collect()
+        };
+
+        let rotation = self
+            .rotation
+            .iter()
+            .flat_map(|track| &track.keyframes)
+            .map(|keyframe| {
+                let (axis, angle) = keyframe
+                    .value
+                    .axis_angle()
+                    .map(|(axis, angle)| (axis.into_inner().into(), angle))
+                    .unwrap_or(([0.0, 1.0, 0.0], 0.0));
+                JsonRotationKeyframe {
+                    time: keyframe.time,
+                    axis,
+                    angle,
+                }
+            })
+            .collect();
+
+        let animation = JsonAnimation {
+            interpolation: self.interpolation,
+            easing: self.easing,
+            playback: self.playback_mode,
+            translation: vector_keyframes(&self.translation),
+            rotation,
+            scale: vector_keyframes(&self.scale),
+        };
+        serde_json::to_value(animation).unwrap()
+    }
+
+    pub fn update(&mut self, delta_time: f64) {
+        self.time += delta_time;
+    }
+
+    pub fn get_duration(&self) -> f64 {
+        let durations = [
+            self.translation.as_ref().map(Track::get_duration),
+            self.rotation.as_ref().map(Track::get_duration),
+            self.scale.as_ref().map(Track::get_duration),
+        ];
+        durations.into_iter().flatten().fold(0.0, f64::max)
+    }
+
+    fn get_local_time(&self) -> f64 {
+        self.playback_mode.wrap_time(self.time, self.get_duration())
+    }
+
+    pub fn get_translation(&self) -> Vector3<f64> {
+        self.translation
+            .as_ref()
+            .map(|track| track.sample(self.get_local_time(), self.interpolation, self.easing))
+            .unwrap_or_else(Vector3::zeros)
+    }
+
+    pub fn get_rotation(&self) -> UnitQuaternion<f64> {
+        self.rotation
+            .as_ref()
+            .map(|track| track.sample(self.get_local_time(), self.interpolation, self.easing))
+            .unwrap_or_else(UnitQuaternion::identity)
+    }
+
+    pub fn get_scale(&self) -> Vector3<f64> {
+        self.scale
+            .as_ref()
+            .map(|track| track.sample(self.get_local_time(), self.interpolation, self.easing))
+            .unwrap_or_else(|| Vector3::new(1.0, 1.0, 1.0))
+    }
+}
+
+fn keyframe_path(track_name: &str, index: usize) -> String {
+    format!("{}[{}]", track_name, index)
+}
+
+// Times that aren't finite can't be sorted or sampled
+fn validate_time(time: f64) -> Result<(), String> {
+    if time.is_finite() {
+        Ok(())
+    } else {
+        Err(format!("time must be finite, got {}", time))
+    }
+}
+
+// Plays an animation on top of any entity's own transform and behavior
+pub struct AnimatedEntity<'a> {
+    entity: Box<dyn Entity + 'a>,
+    animation: Animation,
+}
+
+impl<'a> AnimatedEntity<'a> {
+    pub fn new(entity: Box<dyn Entity + 'a>, animation: Animation) -> Self {
+        AnimatedEntity { entity, animation }
+    }
+
+    pub fn get_animation(&self) -> &Animation {
+        &self.animation
+    }
+}
+
+impl Entity for AnimatedEntity<'_> {
+    fn update(&mut self, delta_time: f64) {
+        self.entity.update(delta_time);
+        self.animation.update(delta_time);
+    }
+
+    fn set_view_position(&mut self, view_position: Point3<f64>) {
+        self.entity.set_view_position(view_position);
+    }
+
+    fn get_id(&self) -> Option<&str> {
+        self.entity.get_id()
+    }
+
+    fn get_origin(&self) -> Point3<f64> {
+        self.entity.get_origin() + self.animation.get_translation()
+    }
+
+    fn get_rotation(&self) -> Rotation3<f64> {
+        self.animation.get_rotation().to_rotation_matrix() * self.entity.get_rotation()
+    }
+
+    fn get_scale(&self) -> Matrix4<f64> {
+        self.entity.get_scale() * Matrix4::new_nonuniform_scaling(&self.animation.get_scale())
+    }
+
+    fn get_model(&self) -> &Model {
+        self.entity.get_model()
+    }
+
+    fn get_transform(&self) -> Option<Transform> {
+        self.entity.get_transform()
+    }
+
+    fn set_transform(&mut self, transform: Transform) {
+        self.entity.set_transform(transform);
+    }
+
+    fn get_maybe_color(&self) -> Option<Color> {
+        self.entity.get_maybe_color()
+    }
+
+    fn to_json(&self) -> Option<serde_json::Value> {
+        let mut json = self.entity.to_json()?;
+        json.as_object_mut()?
+            .insert("animation".to_string(), self.animation.to_json());
+        Some(json)
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct JsonAnimation {
+    #[serde(default)]
+    interpolation: TrackInterpolation,
+    #[serde(default)]
+    easing: Easing,
+    #[serde(default)]
+    playback: PlaybackMode,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    translation: Vec<JsonVectorKeyframe>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    rotation: Vec<JsonRotationKeyframe>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    scale: Vec<JsonVectorKeyframe>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct JsonVectorKeyframe {
+    time: f64,
+    value: [f64; 3],
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct JsonRotationKeyframe {
+    time: f64,
+    axis: [f64; 3],
+    angle: f64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::f64::consts::PI;
+
+    fn vector_track(keyframes: &[(f64, f64)]) -> Track<Vector3<f64>> {
+        Track::new(
+            keyframes
+                .iter()
+                .map(|(time, x)| Keyframe {
+                    time: *time,
+                    value: Vector3::new(*x, 0.0, 0.0),
+                })
+                .collect(),
+        )
+    }
+
+    fn sample_x(
+        track: &Track<Vector3<f64>>,
+        time: f64,
+        interpolation: TrackInterpolation,
+        easing: Easing,
+    ) -> f64 {
+        track.sample(time, interpolation, easing).x
+    }
+
+    #[test]
+    fn tracks_hold_the_end_values_outside_their_keyframes() {
+        // Keyframes are sorted by time
+        let track = vector_track(&[(3.0, 2.0), (1.0, 0.0)]);
+        for interpolation in [
+            TrackInterpolation::Step,
+            TrackInterpolation::Linear,
+            TrackInterpolation::Cubic,
+        ] {
+            assert_eq!(sample_x(&track, 0.0, interpolation, Easing::Linear), 0.0);
+            assert_eq!(sample_x(&track, 5.0, interpolation, Easing::Linear), 2.0);
+        }
+        assert_eq!(track.get_duration(), 3.0);
+    }
+
+    #[test]
+    fn step_tracks_hold_each_keyframe_until_the_next() {
+        let track = vector_track(&[(0.0, 0.0), (1.0, 1.0), (2.0, 4.0)]);
+        let step = TrackInterpolation::Step;
+        assert_eq!(sample_x(&track, 0.99, step, Easing::Linear), 0.0);
+        assert_eq!(sample_x(&track, 1.0, step, Easing::Linear), 1.0);
+        assert_eq!(sample_x(&track, 1.5, step, Easing::Linear), 1.0);
+    }
+
+    #[test]
+    fn linear_tracks_ease_between_keyframes() {
+        let track = vector_track(&[(0.0, 0.0), (2.0, 4.0)]);
+        let linear = TrackInterpolation::Linear;
+        assert_eq!(sample_x(&track, 1.0, linear, Easing::Linear), 2.0);
+        assert_eq!(sample_x(&track, 1.0, linear, Easing::EaseIn), 1.0);
+        assert_eq!(sample_x(&track, 1.0, linear, Easing::EaseOut), 3.0);
+    }
+
+    #[test]
+    fn cubic_tracks_pass_through_the_keyframes() {
+        let track = vector_track(&[(0.0, 0.0), (1.0, 1.0), (2.0, 2.0), (3.0, 3.0)]);
+        let cubic = TrackInterpolation::Cubic;
+        for time in [0.0, 1.0, 2.0, 3.0] {
+            assert_eq!(sample_x(&track, time, cubic, Easing::Linear), time);
+        }
+        // Evenly spaced keyframes on a line give a straight spline between the inner ones
+        assert_eq!(sample_x(&track, 1.5, cubic, Easing::Linear), 1.5);
+    }
+
+    #[test]
+    fn rotation_tracks_take_the_short_way_around() {
+        let quarter_turn = UnitQuaternion::from_axis_angle(&Vector3::y_axis(), PI / 2.0);
+        let track = Track::new(vec![
+            Keyframe {
+                time: 0.0,
+                value: UnitQuaternion::identity(),
+            },
+            Keyframe {
+                time: 1.0,
+                // The same rotation, with the quaternion in the other hemisphere
+                value: UnitQuaternion::new_unchecked(-quarter_turn.into_inner()),
+            },
+        ]);
+        let eighth_turn = UnitQuaternion::from_axis_angle(&Vector3::y_axis(), PI / 4.0);
+        for interpolation in [TrackInterpolation::Linear, TrackInterpolation::Cubic] {
+            let rotation = track.sample(0.5, interpolation, Easing::Linear);
+            assert!(rotation.angle_to(&eighth_turn) < 1e-9);
+        }
+    }
+
+    #[test]
+    fn animations_wrap_their_time_by_the_playback_mode() {
+        let json = json!({
+            "playback": "ping_pong",
+            "translation": [
+                { "time": 0.0, "value": [0.0, 0.0, 0.0] },
+                { "time": 2.0, "value": [4.0, 0.0, 0.0] }
+            ]
+        });
+        let mut animation = Animation::from_json(&json).unwrap();
+        animation.update(3.0);
+
+        assert_eq!(animation.get_duration(), 2.0);
+        assert_eq!(animation.get_translation(), Vector3::new(2.0, 0.0, 0.0));
+        assert_eq!(animation.get_rotation(), UnitQuaternion::identity());
+        assert_eq!(animation.get_scale(), Vector3::new(1.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn rejects_zero_rotation_axes() {
+        let json = json!({
+            "rotation": [
+                { "time": 0.0, "axis": [0.0, 1.0, 0.0], "angle": 0.0 },
+                { "time": 1.0, "axis": [0.0, 0.0, 0.0], "angle": 1.0 }
+            ]
+        });
+        let error = Animation::from_json(&json).unwrap_err();
+
+        assert_eq!(error.path, "rotation[1]");
+        assert_eq!(
+            error.message,
+            "axis must be a finite, non-zero vector, got [0.0, 0.0, 0.0]"
+        );
+    }
+
+    #[test]
+    fn rejects_times_that_are_not_finite() {
+        // JSON numbers are always finite, and serde_json turns infinite ones into null
+        let json = json!({ "scale": [{ "time": f64::INFINITY, "value": [1.0, 1.0, 1.0] }] });
+        assert!(Animation::from_json(&json).is_err());
+
+        assert_eq!(
+            validate_time(f64::NAN),
+            Err("time must be finite, got NaN".to_string())
+        );
+        assert_eq!(validate_time(1.5), Ok(()));
+    }
+
+    #[test]
+    fn rejects_animations_without_tracks() {
+        let error = Animation::from_json(&json!({ "playback": "once" })).unwrap_err();
+        assert_eq!(error.path, "");
+        assert_eq!(
+            error.message,
+            "Animation requires a translation, rotation or scale track"
+        );
+    }
+}
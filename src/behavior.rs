@@ -0,0 +1,342 @@
+use std::f64::consts::PI;
+
+use nalgebra::{Matrix4, Point3, Rotation3, Unit, Vector3};
+use serde::{Deserialize, Serialize};
+
+use crate::entity::{validate_axis, Entity, Transform};
+use crate::geometry::{Color, Model};
+use crate::interpolation::PlaybackMode;
+
+/*
+    Procedural motion attached to an entity in the scene file. Behaviors are applied in
+    order to the entity's own origin and rotation, so several can be combined, e.g. an
+    oscillation on top of an orbit. Positions are in the entity's parent space.
+*/
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Behavior {
+    // Circles the origin around the center point
+    Orbit {
+        center: [f64; 3],
+        #[serde(default = "default_axis")]
+        axis: [f64; 3],
+        angular_velocity: f64,
+        #[serde(default)]
+        phase: f64,
+    },
+    // Moves back and forth along the axis in a sine wave
+    Oscillate {
+        #[serde(default = "default_axis")]
+        axis: [f64; 3],
+        amplitude: f64,
+        // Cycles per second
+        frequency: f64,
+        #[serde(default)]
+        phase: f64,
+    },
+    // Travels along a polyline of points offset from the origin at a constant speed,
+    // looping back to the first point when closed, or turning back at the ends otherwise
+    FollowPath {
+        points: Vec<[f64; 3]>,
+        speed: f64,
+        #[serde(default)]
+        closed: bool,
+        // Turns the model's +z axis to face along the path
+        #[serde(default)]
+        orient: bool,
+    },
+    // Turns the model's +z axis to face the camera, for billboards
+    LookAtCamera {
+        #[serde(default = "default_axis")]
+        up: [f64; 3],
+    },
+}
+
+fn default_axis() -> [f64; 3] {
+    [0.0, 1.0, 0.0]
+}
+
+impl Behavior {
+    // Checks the parameters that the scene file format can't express
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            Behavior::FollowPath { points, .. } if points.is_empty() => {
+                Err("follow_path requires at least one point".to_string())
+            }
+            Behavior::Orbit { axis, .. } | Behavior::Oscillate { axis, .. } => {
+                validate_axis("axis", axis)
+            }
+            Behavior::LookAtCamera { up } => validate_axis("up", up),
+            _ => Ok(()),
+        }
+    }
+
+    fn apply(
+        &self,
+        time: f64,
+        view_position: &Point3<f64>,
+        origin: &mut Point3<f64>,
+        rotation: &mut Rotation3<f64>,
+    ) {
+        match self {
+            Behavior::Orbit {
+                center,
+                axis,
+                angular_velocity,
+                phase,
+            } => {
+                let center = Point3::from(*center);
+                let orbit_rotation = Rotation3::from_axis_angle(
+                    &Unit::new_normalize(Vector3::from(*axis)),
+                    angular_velocity * time + phase,
+                );
+                *origin = center + orbit_rotation * (*origin - center);
+            }
+            Behavior::Oscillate {
+                axis,
+                amplitude,
+                frequency,
+                phase,
+            } => {
+                let offset = (2.0 * PI * frequency * time + phase).sin() * amplitude;
+                *origin += Vector3::from(*axis).normalize() * offset;
+            }
+            Behavior::FollowPath {
+                points,
+                speed,
+                closed,
+                orient,
+            } => {
+                let (offset, direction) = sample_path(points, *closed, speed * time);
+                *origin += offset;
+                if *orient {
+                    if let Some(direction) = direction {
+                        // +y can't be up along a vertical segment, so +z is used there instead
+                        let up = if direction.cross(&Vector3::y()).magnitude_squared() > 1e-12 {
+                            Vector3::y()
+                        } else {
+                            Vector3::z()
+                        };
+                        *rotation = Rotation3::face_towards(&direction, &up) * *rotation;
+                    }
+                }
+            }
+            Behavior::LookAtCamera { up } => {
+                let direction = view_position - *origin;
+                let up = Vector3::from(*up);
+                if direction.cross(&up).magnitude_squared() > 1e-12 {
+                    *rotation = Rotation3::face_towards(&direction, &up) * *rotation;
+                }
+            }
+        }
+    }
+}
+
+// Position along the polyline after travelling the distance, and the direction of travel
+fn sample_path(
+    points: &[[f64; 3]],
+    closed: bool,
+    distance: f64,
+) -> (Vector3<f64>, Option<Vector3<f64>>) {
+    let mut points: Vec<Vector3<f64>> = points.iter().map(|point| Vector3::from(*point)).collect();
+    if closed {
+        points.push(points[0]);
+    }
+
+    let lengths: Vec<f64> = points
+        .windows(2)
+        .map(|segment| (segment[1] - segment[0]).magnitude())
+        .collect();
+    let total_length: f64 = lengths.iter().sum();
+    if total_length <= 0.0 {
+        return (points[0], None);
+    }
+
+    let playback_mode = if closed {
+        PlaybackMode::Loop
+    } else {
+        PlaybackMode::PingPong
+    };
+    let wrapped_distance = playback_mode.wrap_time(distance, total_length);
+    // Heading back along an open path after reaching its end
+    let is_reversed = !closed && distance.rem_euclid(2.0 * total_length) > total_length;
+
+    let mut remaining = wrapped_distance;
+    for (index, length) in lengths.iter().enumerate() {
+        if remaining <= *length || index == lengths.len() - 1 {
+            let t = if *length > 0.0 {
+                (remaining / length).min(1.0)
+            } else {
+                0.0
+            };
+            let start = points[index];
+            let end = points[index + 1];
+            let direction = (end - start).try_normalize(1e-12).map(|direction| {
+                if is_reversed {
+                    -direction
+                } else {
+                    direction
+                }
+            });
+            return (start + (end - start) * t, direction);
+        }
+        remaining -= length;
+    }
+
+    (points[0], None)
+}
+
+// Applies behaviors on top of any entity's own transform and animation
+pub struct BehaviorEntity<'a> {
+    entity: Box<dyn Entity + 'a>,
+    behaviors: Vec<Behavior>,
+    time: f64,
+    view_position: Point3<f64>,
+}
+
+impl<'a> BehaviorEntity<'a> {
+    pub fn new(entity: Box<dyn Entity + 'a>, behaviors: Vec<Behavior>) -> Self {
+        BehaviorEntity {
+            entity,
+            behaviors,
+            time: 0.0,
+            view_position: Point3::origin(),
+        }
+    }
+
+    pub fn get_behaviors(&self) -> &[Behavior] {
+        &self.behaviors
+    }
+
+    // The entity's origin and rotation with the behaviors applied in order
+    fn apply_behaviors(&self) -> (Point3<f64>, Rotation3<f64>) {
+        let mut origin = self.entity.get_origin();
+        let mut rotation = self.entity.get_rotation();
+        for behavior in &self.behaviors {
+            behavior.apply(self.time, &self.view_position, &mut origin, &mut rotation);
+        }
+        (origin, rotation)
+    }
+}
+
+impl Entity for BehaviorEntity<'_> {
+    fn update(&mut self, delta_time: f64) {
+        self.entity.update(delta_time);
+        self.time += delta_time;
+    }
+
+    fn set_view_position(&mut self, view_position: Point3<f64>) {
+        self.entity.set_view_position(view_position);
+        self.view_position = view_position;
+    }
+
+    fn get_id(&self) -> Option<&str> {
+        self.entity.get_id()
+    }
+
+    fn get_origin(&self) -> Point3<f64> {
+        self.apply_behaviors().0
+    }
+
+    fn get_rotation(&self) -> Rotation3<f64> {
+        self.apply_behaviors().1
+    }
+
+    fn get_scale(&self) -> Matrix4<f64> {
+        self.entity.get_scale()
+    }
+
+    fn get_model(&self) -> &Model {
+        self.entity.get_model()
+    }
+
+    fn get_transform(&self) -> Option<Transform> {
+        self.entity.get_transform()
+    }
+
+    fn set_transform(&mut self, transform: Transform) {
+        self.entity.set_transform(transform);
+    }
+
+    fn get_maybe_color(&self) -> Option<Color> {
+        self.entity.get_maybe_color()
+    }
+
+    fn to_json(&self) -> Option<serde_json::Value> {
+        let mut json = self.entity.to_json()?;
+        let behaviors = serde_json::to_value(&self.behaviors).ok()?;
+        json.as_object_mut()?
+            .insert("behaviors".to_string(), behaviors);
+        Some(json)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply(behavior: &Behavior, time: f64) -> (Point3<f64>, Rotation3<f64>) {
+        let mut origin = Point3::origin();
+        let mut rotation = Rotation3::identity();
+        behavior.apply(
+            time,
+            &Point3::new(0.0, 0.0, 5.0),
+            &mut origin,
+            &mut rotation,
+        );
+        (origin, rotation)
+    }
+
+    #[test]
+    fn oriented_paths_face_along_vertical_segments() {
+        let behavior = Behavior::FollowPath {
+            points: vec![[0.0, 0.0, 0.0], [0.0, 1.0, 0.0]],
+            speed: 1.0,
+            closed: false,
+            orient: true,
+        };
+        for (time, direction) in [(0.5, Vector3::y()), (1.5, -Vector3::y())] {
+            let (origin, rotation) = apply(&behavior, time);
+            assert_eq!(origin, Point3::new(0.0, 0.5, 0.0));
+            assert!((rotation * Vector3::z() - direction).magnitude() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn rejects_zero_axes() {
+        let behaviors = [
+            Behavior::Orbit {
+                center: [0.0, 0.0, 0.0],
+                axis: [0.0, 0.0, 0.0],
+                angular_velocity: 1.0,
+                phase: 0.0,
+            },
+            Behavior::Oscillate {
+                axis: [0.0, 0.0, 0.0],
+                amplitude: 1.0,
+                frequency: 1.0,
+                phase: 0.0,
+            },
+        ];
+        for behavior in behaviors {
+            assert_eq!(
+                behavior.validate(),
+                Err("axis must be a finite, non-zero vector, got [0.0, 0.0, 0.0]".to_string())
+            );
+        }
+        assert_eq!(
+            Behavior::LookAtCamera {
+                up: [0.0, f64::NAN, 0.0]
+            }
+            .validate(),
+            Err("up must be a finite, non-zero vector, got [0.0, NaN, 0.0]".to_string())
+        );
+        assert_eq!(
+            Behavior::LookAtCamera {
+                up: [0.0, 1.0, 0.0]
+            }
+            .validate(),
+            Ok(())
+        );
+    }
+}
@@ -1,39 +1,94 @@
-use nalgebra::{Matrix4, Point3, Vector3};
+use nalgebra::{Matrix3, Matrix4, Point3, Rotation3, UnitQuaternion, Vector3};
+
+use crate::scene_graph::SceneGraph;
+use crate::terminal::key_bindings::Action;
+use crate::terminal::mouse::MouseState;
 
 pub trait Camera {
-    fn get_view_projection_matrix(&self) -> Matrix4<f64>;
+    fn update(&mut self, delta_time: f64, actions: &[Action], mouse_state: &MouseState);
+
+    // Called once for each action whose key was newly pressed, for camera specific toggles
+    fn trigger_action(&mut self, _action: Action) {}
+
+    // Called each frame after the scene entities update, for cameras that follow entities
+    fn update_from_scene(&mut self, _scene_graph: &SceneGraph) {}
+
+    fn get_position(&self) -> Point3<f64>;
+
+    // Unit vector pointing in the view direction
+    fn get_forward(&self) -> Vector3<f64>;
+
+    fn get_view_matrix(&self) -> Matrix4<f64>;
+
+    fn get_projection_matrix(&self) -> Matrix4<f64>;
+
+    fn set_aspect_ratio(&mut self, aspect_ratio: f64);
+
+    // The object describing the camera in a scene file's cameras, so the view can be saved.
+    // Cameras that can't be described return None
+    fn to_json(&self) -> Option<serde_json::Value> {
+        None
+    }
+
+    fn get_view_projection_matrix(&self) -> Matrix4<f64> {
+        self.get_projection_matrix() * self.get_view_matrix()
+    }
+
+    // Position and orientation recovered from the view matrix, including any roll
+    fn get_pose(&self) -> Pose {
+        let view = self.get_view_matrix();
+        let rotation: Matrix3<f64> = view.fixed_view::<3, 3>(0, 0).transpose();
+        let orientation = UnitQuaternion::from_rotation_matrix(&Rotation3::from_matrix(&rotation));
+        Pose::new(self.get_position(), orientation)
+    }
 }
 
 mod controllable_perspective_camera;
 pub use controllable_perspective_camera::ControllablePerspectiveCamera;
 pub use controllable_perspective_camera::ControllablePerspectiveCameraBuilder;
 
+mod follow_perspective_camera;
+pub use follow_perspective_camera::FollowMode;
+pub use follow_perspective_camera::FollowPerspectiveCamera;
+pub use follow_perspective_camera::FollowPerspectiveCameraBuilder;
+
+mod orbit_control_perspective_camera;
+pub use orbit_control_perspective_camera::OrbitControlPerspectiveCamera;
+pub use orbit_control_perspective_camera::OrbitControlPerspectiveCameraBuilder;
+
 mod orbiting_perspective_camera;
 pub use orbiting_perspective_camera::OrbitingPerspectiveCamera;
 pub use orbiting_perspective_camera::OrbitingPerspectiveCameraBuilder;
 
+mod orthographic_camera;
+pub use orthographic_camera::OrthographicCamera;
+pub use orthographic_camera::OrthographicCameraBuilder;
+pub use orthographic_camera::OrthographicPreset;
+
+mod path_perspective_camera;
+pub use path_perspective_camera::CameraKeyframe;
+pub use path_perspective_camera::PathInterpolation;
+pub use path_perspective_camera::PathPerspectiveCamera;
+pub use path_perspective_camera::PathPerspectiveCameraBuilder;
+
+mod pose;
+pub use pose::Pose;
+
+mod walk_controller;
+pub use walk_controller::WalkController;
+
 mod static_perspective_camera;
 pub use static_perspective_camera::StaticPerspectiveCamera;
 pub use static_perspective_camera::StaticPerspectiveCameraBuilder;
 
-pub(crate) fn get_view_projection_matrix(
-    projection_matrix: &Matrix4<f64>,
-    origin: Point3<f64>,
-    yaw: f64,
-    pitch: f64,
-) -> Matrix4<f64> {
-    let direction = Vector3::new(
+pub(crate) fn get_view_matrix(origin: Point3<f64>, yaw: f64, pitch: f64) -> Matrix4<f64> {
+    Pose::from_yaw_pitch_roll(origin, yaw, pitch, 0.0).get_view_matrix()
+}
+
+pub(crate) fn get_direction(yaw: f64, pitch: f64) -> Vector3<f64> {
+    Vector3::new(
         yaw.cos() * pitch.cos(),
         pitch.sin(),
         yaw.sin() * pitch.cos(),
-    );
-
-    let global_up = Vector3::y_axis();
-
-    let right = direction.cross(&global_up).normalize();
-    let up = right.cross(&direction);
-
-    let view_matrix = Matrix4::look_at_rh(&origin, &(origin + direction), &up);
-
-    projection_matrix * view_matrix
+    )
 }
@@ -3,17 +3,29 @@
 use std::f64::consts::PI;
 
 use nalgebra::{Matrix4, Perspective3, Point3, Rotation3, Vector3};
+use serde_json::json;
 
-use crate::camera::Camera;
-use crate::terminal::keyboard::Keys;
+use crate::camera::{Camera, Pose, WalkController};
+use crate::scene_graph::SceneGraph;
+use crate::terminal::key_bindings::Action;
+use crate::terminal::mouse::MouseState;
 
 pub struct ControllablePerspectiveCamera {
     origin: Point3<f64>,
     yaw: f64,
     pitch: f64,
+    roll: f64,
     linear_speed: f64,
     angular_speed: f64,
-    projection_matrix: Matrix4<f64>,
+    mouse_sensitivity: f64,
+    zoom_speed: f64,
+    is_walking: bool,
+    walk_controller: WalkController,
+    // Kept since the projection matrix only gives them back with rounding errors
+    fov: f64,
+    near_plane: f64,
+    far_plane: f64,
+    projection: Perspective3<f64>,
 }
 
 impl ControllablePerspectiveCamera {
@@ -27,55 +39,152 @@ impl ControllablePerspectiveCamera {
         far_plane: f64,
         linear_speed: f64,
         angular_speed: f64,
+        mouse_sensitivity: f64,
+        zoom_speed: f64,
+        is_walking: bool,
     ) -> Self {
-        let projection_matrix =
-            Perspective3::new(aspect_ratio, fov, near_plane, far_plane).to_homogeneous();
+        let projection = Perspective3::new(aspect_ratio, fov, near_plane, far_plane);
+        let mut walk_controller = WalkController::default();
+        walk_controller.reset(origin);
         ControllablePerspectiveCamera {
             origin,
             yaw,
             pitch,
+            roll: 0.0,
             linear_speed,
             angular_speed,
-            projection_matrix,
+            mouse_sensitivity,
+            zoom_speed,
+            is_walking,
+            walk_controller,
+            fov,
+            near_plane,
+            far_plane,
+            projection,
         }
     }
 
-    pub fn update(&mut self, delta_time: f64, pressed_keys: &[Keys]) {
+    // Walk mode falls onto and collides with the scene geometry, jumping with move up
+    pub fn set_walking(&mut self, is_walking: bool) {
+        self.is_walking = is_walking;
+        self.walk_controller.reset(self.origin);
+    }
+
+    pub fn is_walking(&self) -> bool {
+        self.is_walking
+    }
+}
+
+impl Camera for ControllablePerspectiveCamera {
+    fn update(&mut self, delta_time: f64, actions: &[Action], mouse_state: &MouseState) {
         let mut velocity = Vector3::new(0.0, 0.0, 0.0);
         let mut yaw_velocity: f64 = 0.0;
         let mut pitch_velocity: f64 = 0.0;
+        let mut roll_velocity: f64 = 0.0;
 
-        pressed_keys.iter().for_each(|key| match key {
-            Keys::W => velocity += Vector3::new(0.0, 0.0, -self.linear_speed),
-            Keys::A => velocity += Vector3::new(-self.linear_speed, 0.0, 0.0),
-            Keys::S => velocity += Vector3::new(0.0, 0.0, self.linear_speed),
-            Keys::D => velocity += Vector3::new(self.linear_speed, 0.0, 0.0),
-            Keys::C => velocity += Vector3::new(0.0, -self.linear_speed, 0.0),
-            Keys::Space => velocity += Vector3::new(0.0, self.linear_speed, 0.0),
-            Keys::Left => yaw_velocity += -self.angular_speed,
-            Keys::Right => yaw_velocity += self.angular_speed,
-            Keys::Up => pitch_velocity += self.angular_speed,
-            Keys::Down => pitch_velocity += -self.angular_speed,
+        actions.iter().for_each(|action| match action {
+            Action::MoveForward => velocity += Vector3::new(0.0, 0.0, -self.linear_speed),
+            Action::MoveLeft => velocity += Vector3::new(-self.linear_speed, 0.0, 0.0),
+            Action::MoveBackward => velocity += Vector3::new(0.0, 0.0, self.linear_speed),
+            Action::MoveRight => velocity += Vector3::new(self.linear_speed, 0.0, 0.0),
+            Action::MoveDown => velocity += Vector3::new(0.0, -self.linear_speed, 0.0),
+            Action::MoveUp => velocity += Vector3::new(0.0, self.linear_speed, 0.0),
+            Action::YawLeft => yaw_velocity += -self.angular_speed,
+            Action::YawRight => yaw_velocity += self.angular_speed,
+            Action::PitchUp => pitch_velocity += self.angular_speed,
+            Action::PitchDown => pitch_velocity += -self.angular_speed,
+            Action::RollLeft => roll_velocity += -self.angular_speed,
+            Action::RollRight => roll_velocity += self.angular_speed,
             _ => {}
         });
 
-        self.yaw += yaw_velocity * delta_time;
-        self.pitch += pitch_velocity * delta_time;
-        self.pitch = self.pitch.clamp(-1.5, 1.5);
+        // A screen pixel is two terminal columns wide, but only one row tall
+        let (drag_x, drag_y) = mouse_state.drag_delta;
+        self.yaw += yaw_velocity * delta_time + drag_x / 2.0 * self.mouse_sensitivity;
+        self.pitch += pitch_velocity * delta_time - drag_y * self.mouse_sensitivity;
+        // The quaternion pose has no trouble looking straight up or down, the clamp only
+        // stops the camera from flipping over the top
+        self.pitch = self.pitch.clamp(-PI / 2.0, PI / 2.0);
+        self.roll += roll_velocity * delta_time;
 
         let rotation = Rotation3::from_euler_angles(0.0, -self.yaw - PI / 2.0, 0.0);
+        let direction = super::get_direction(self.yaw, self.pitch);
+
+        if self.is_walking {
+            // Vertical movement comes from gravity and jumping, so only the horizontal
+            // parts of the movement and scroll dolly are kept
+            let mut displacement =
+                rotation * Vector3::new(velocity.x, 0.0, velocity.z) * delta_time
+                    + direction * mouse_state.scroll_delta * self.zoom_speed;
+            displacement.y = 0.0;
+
+            let jump = actions.contains(&Action::MoveUp);
+            self.walk_controller
+                .step(&mut self.origin, displacement, jump, delta_time);
+            return;
+        }
+
         self.origin += rotation * velocity * delta_time;
+
+        // Scrolling dollies the camera along the view direction
+        self.origin += direction * mouse_state.scroll_delta * self.zoom_speed;
     }
-}
 
-impl Camera for ControllablePerspectiveCamera {
-    fn get_view_projection_matrix(&self) -> Matrix4<f64> {
-        super::get_view_projection_matrix(
-            &self.projection_matrix,
-            self.origin,
-            self.yaw,
-            self.pitch,
-        )
+    fn trigger_action(&mut self, action: Action) {
+        if action == Action::ToggleWalkMode {
+            self.set_walking(!self.is_walking);
+        }
+    }
+
+    // Taken every frame, since animated entities move, but each step of the walk controller
+    // only tests the triangles near its movement
+    fn update_from_scene(&mut self, scene_graph: &SceneGraph) {
+        if self.is_walking {
+            self.walk_controller
+                .set_triangles(scene_graph.get_all_world_triangles());
+        }
+    }
+
+    fn get_position(&self) -> Point3<f64> {
+        self.origin
+    }
+
+    fn get_forward(&self) -> Vector3<f64> {
+        super::get_direction(self.yaw, self.pitch)
+    }
+
+    fn get_view_matrix(&self) -> Matrix4<f64> {
+        self.get_pose().get_view_matrix()
+    }
+
+    fn get_projection_matrix(&self) -> Matrix4<f64> {
+        self.projection.to_homogeneous()
+    }
+
+    fn set_aspect_ratio(&mut self, aspect_ratio: f64) {
+        self.projection.set_aspect(aspect_ratio);
+    }
+
+    fn get_pose(&self) -> Pose {
+        Pose::from_yaw_pitch_roll(self.origin, self.yaw, self.pitch, self.roll)
+    }
+
+    fn to_json(&self) -> Option<serde_json::Value> {
+        Some(json!({
+            "type": "controllable",
+            "position": self.origin.coords.as_slice(),
+            "yaw": self.yaw,
+            "pitch": self.pitch,
+            "roll": self.roll,
+            "fov": self.fov,
+            "near_plane": self.near_plane,
+            "far_plane": self.far_plane,
+            "linear_speed": self.linear_speed,
+            "angular_speed": self.angular_speed,
+            "mouse_sensitivity": self.mouse_sensitivity,
+            "zoom_speed": self.zoom_speed,
+            "walk_mode": self.is_walking,
+        }))
     }
 }
 
@@ -83,12 +192,16 @@ pub struct ControllablePerspectiveCameraBuilder {
     origin: Point3<f64>,
     yaw: f64,
     pitch: f64,
+    roll: f64,
     aspect_ratio: f64,
     fov: f64,
     near_plane: f64,
     far_plane: f64,
     linear_speed: f64,
     angular_speed: f64,
+    mouse_sensitivity: f64,
+    zoom_speed: f64,
+    is_walking: bool,
 }
 
 impl Default for ControllablePerspectiveCameraBuilder {
@@ -103,12 +216,16 @@ impl ControllablePerspectiveCameraBuilder {
             origin: Point3::new(0.0, 0.0, 0.0),
             yaw: -std::f64::consts::PI / 2.0,
             pitch: -0.4,
+            roll: 0.0,
             fov: 1.0,
             aspect_ratio: 0.4,
             near_plane: 0.01,
             far_plane: 1000.0,
             linear_speed: 1.0,
             angular_speed: 0.2,
+            mouse_sensitivity: 0.02,
+            zoom_speed: 0.2,
+            is_walking: false,
         }
     }
 
@@ -130,6 +247,13 @@ impl ControllablePerspectiveCameraBuilder {
         self
     }
 
+    // Bank angle in radians, clockwise around the view direction
+    #[allow(unused)]
+    pub fn roll(&mut self, roll: f64) -> &mut Self {
+        self.roll = roll;
+        self
+    }
+
     #[allow(unused)]
     pub fn aspect_ratio(&mut self, aspect_ratio: f64) -> &mut Self {
         self.aspect_ratio = aspect_ratio;
@@ -166,9 +290,27 @@ impl ControllablePerspectiveCameraBuilder {
         self
     }
 
+    #[allow(unused)]
+    pub fn mouse_sensitivity(&mut self, mouse_sensitivity: f64) -> &mut Self {
+        self.mouse_sensitivity = mouse_sensitivity;
+        self
+    }
+
+    #[allow(unused)]
+    pub fn zoom_speed(&mut self, zoom_speed: f64) -> &mut Self {
+        self.zoom_speed = zoom_speed;
+        self
+    }
+
+    #[allow(unused)]
+    pub fn walk_mode(&mut self, is_walking: bool) -> &mut Self {
+        self.is_walking = is_walking;
+        self
+    }
+
     #[allow(unused)]
     pub fn build(&mut self) -> ControllablePerspectiveCamera {
-        ControllablePerspectiveCamera::new(
+        let mut camera = ControllablePerspectiveCamera::new(
             self.origin,
             self.yaw,
             self.pitch,
@@ -178,6 +320,11 @@ impl ControllablePerspectiveCameraBuilder {
             self.far_plane,
             self.linear_speed,
             self.angular_speed,
-        )
+            self.mouse_sensitivity,
+            self.zoom_speed,
+            self.is_walking,
+        );
+        camera.roll = self.roll;
+        camera
     }
 }
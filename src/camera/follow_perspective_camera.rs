@@ -0,0 +1,207 @@
+use nalgebra::{Matrix4, Perspective3, Point3, Vector3};
+use serde_json::json;
+
+use crate::camera::{Camera, Pose};
+use crate::scene_graph::SceneGraph;
+use crate::terminal::key_bindings::Action;
+use crate::terminal::mouse::MouseState;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum FollowMode {
+    // Rigidly attached at an offset in the entity's local frame, looking at its origin
+    Chase { offset: Vector3<f64> },
+    // Stays at a fixed position, turning to look at the entity's origin
+    Track { position: Point3<f64> },
+}
+
+/*
+    Camera bound to a scene entity by its id. The pose is recomputed from the entity's
+    origin and rotation every frame after the scene entities update, so the camera
+    keeps up with moving and spinning entities.
+*/
+pub struct FollowPerspectiveCamera {
+    entity_id: String,
+    mode: FollowMode,
+    pose: Pose,
+    // Kept since the projection matrix only gives them back with rounding errors
+    fov: f64,
+    near_plane: f64,
+    far_plane: f64,
+    projection: Perspective3<f64>,
+}
+
+impl FollowPerspectiveCamera {
+    pub fn new(
+        entity_id: String,
+        mode: FollowMode,
+        aspect_ratio: f64,
+        fov: f64,
+        near_plane: f64,
+        far_plane: f64,
+    ) -> Self {
+        let position = match mode {
+            FollowMode::Chase { offset } => Point3::from(offset),
+            FollowMode::Track { position } => position,
+        };
+        FollowPerspectiveCamera {
+            entity_id,
+            mode,
+            pose: Pose::look_at(position, Point3::origin(), &Vector3::y()),
+            fov,
+            near_plane,
+            far_plane,
+            projection: Perspective3::new(aspect_ratio, fov, near_plane, far_plane),
+        }
+    }
+
+    pub fn get_entity_id(&self) -> &str {
+        &self.entity_id
+    }
+}
+
+impl Camera for FollowPerspectiveCamera {
+    fn update(&mut self, _delta_time: f64, _actions: &[Action], _mouse_state: &MouseState) {}
+
+    fn update_from_scene(&mut self, scene_graph: &SceneGraph) {
+        // Keeps the last pose if the entity has been removed from the scene
+        let Some(index) = scene_graph.find_entity(&self.entity_id) else {
+            return;
+        };
+
+        let origin = scene_graph.get_world_origin(index);
+        self.pose = match self.mode {
+            FollowMode::Chase { offset } => {
+                let rotation = scene_graph.get_world_rotation(index);
+                Pose::look_at(
+                    origin + rotation * offset,
+                    origin,
+                    &(rotation * Vector3::y()),
+                )
+            }
+            FollowMode::Track { position } => Pose::look_at(position, origin, &Vector3::y()),
+        };
+    }
+
+    fn get_position(&self) -> Point3<f64> {
+        self.pose.position
+    }
+
+    fn get_forward(&self) -> Vector3<f64> {
+        self.pose.get_forward()
+    }
+
+    fn get_view_matrix(&self) -> Matrix4<f64> {
+        self.pose.get_view_matrix()
+    }
+
+    fn get_projection_matrix(&self) -> Matrix4<f64> {
+        self.projection.to_homogeneous()
+    }
+
+    fn set_aspect_ratio(&mut self, aspect_ratio: f64) {
+        self.projection.set_aspect(aspect_ratio);
+    }
+
+    fn get_pose(&self) -> Pose {
+        self.pose
+    }
+
+    fn to_json(&self) -> Option<serde_json::Value> {
+        let mut json = json!({
+            "type": "follow",
+            "entity": self.entity_id,
+            "fov": self.fov,
+            "near_plane": self.near_plane,
+            "far_plane": self.far_plane,
+        });
+        match self.mode {
+            FollowMode::Chase { offset } => {
+                json["mode"] = json!("chase");
+                json["offset"] = json!(offset.as_slice());
+            }
+            FollowMode::Track { position } => {
+                json["mode"] = json!("track");
+                json["position"] = json!(position.coords.as_slice());
+            }
+        }
+        Some(json)
+    }
+}
+
+pub struct FollowPerspectiveCameraBuilder {
+    entity_id: String,
+    mode: FollowMode,
+    aspect_ratio: f64,
+    fov: f64,
+    near_plane: f64,
+    far_plane: f64,
+}
+
+impl Default for FollowPerspectiveCameraBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FollowPerspectiveCameraBuilder {
+    pub fn new() -> Self {
+        Self {
+            entity_id: String::new(),
+            mode: FollowMode::Chase {
+                offset: Vector3::new(0.0, 1.0, 3.0),
+            },
+            aspect_ratio: 1.6,
+            fov: 1.0,
+            near_plane: 0.01,
+            far_plane: 1000.0,
+        }
+    }
+
+    #[allow(unused)]
+    pub fn entity_id(&mut self, entity_id: &str) -> &mut Self {
+        self.entity_id = entity_id.to_string();
+        self
+    }
+
+    #[allow(unused)]
+    pub fn mode(&mut self, mode: FollowMode) -> &mut Self {
+        self.mode = mode;
+        self
+    }
+
+    #[allow(unused)]
+    pub fn aspect_ratio(&mut self, aspect_ratio: f64) -> &mut Self {
+        self.aspect_ratio = aspect_ratio;
+        self
+    }
+
+    #[allow(unused)]
+    pub fn fov(&mut self, fov: f64) -> &mut Self {
+        self.fov = fov;
+        self
+    }
+
+    #[allow(unused)]
+    pub fn near_plane(&mut self, near_plane: f64) -> &mut Self {
+        self.near_plane = near_plane;
+        self
+    }
+
+    #[allow(unused)]
+    pub fn far_plane(&mut self, far_plane: f64) -> &mut Self {
+        self.far_plane = far_plane;
+        self
+    }
+
+    #[allow(unused)]
+    pub fn build(&mut self) -> FollowPerspectiveCamera {
+        FollowPerspectiveCamera::new(
+            self.entity_id.clone(),
+            self.mode,
+            self.aspect_ratio,
+            self.fov,
+            self.near_plane,
+            self.far_plane,
+        )
+    }
+}
@@ -0,0 +1,268 @@
+#![allow(clippy::too_many_arguments)]
+
+use std::f64::consts::PI;
+
+use nalgebra::{Matrix4, Perspective3, Point3, Vector3};
+use serde_json::json;
+
+use crate::camera::Camera;
+use crate::terminal::key_bindings::Action;
+use crate::terminal::mouse::MouseState;
+
+/*
+    Model viewer style camera. The camera sits on a sphere around the target point,
+    dragging the mouse rotates the camera around the target and scrolling zooms in and out.
+*/
+pub struct OrbitControlPerspectiveCamera {
+    target: Point3<f64>,
+    distance: f64,
+    yaw: f64,
+    pitch: f64,
+    angular_speed: f64,
+    mouse_sensitivity: f64,
+    zoom_speed: f64,
+    min_distance: f64,
+    max_distance: f64,
+    // Kept since the projection matrix only gives them back with rounding errors
+    fov: f64,
+    near_plane: f64,
+    far_plane: f64,
+    projection: Perspective3<f64>,
+}
+
+impl OrbitControlPerspectiveCamera {
+    pub fn new(
+        target: Point3<f64>,
+        distance: f64,
+        yaw: f64,
+        pitch: f64,
+        aspect_ratio: f64,
+        fov: f64,
+        near_plane: f64,
+        far_plane: f64,
+        angular_speed: f64,
+        mouse_sensitivity: f64,
+        zoom_speed: f64,
+        min_distance: f64,
+        max_distance: f64,
+    ) -> Self {
+        let projection = Perspective3::new(aspect_ratio, fov, near_plane, far_plane);
+        OrbitControlPerspectiveCamera {
+            target,
+            distance: distance.clamp(min_distance, max_distance),
+            yaw,
+            pitch,
+            angular_speed,
+            mouse_sensitivity,
+            zoom_speed,
+            min_distance,
+            max_distance,
+            fov,
+            near_plane,
+            far_plane,
+            projection,
+        }
+    }
+}
+
+impl Camera for OrbitControlPerspectiveCamera {
+    fn update(&mut self, delta_time: f64, actions: &[Action], mouse_state: &MouseState) {
+        let mut yaw_velocity: f64 = 0.0;
+        let mut pitch_velocity: f64 = 0.0;
+
+        actions.iter().for_each(|action| match action {
+            Action::YawLeft => yaw_velocity += -self.angular_speed,
+            Action::YawRight => yaw_velocity += self.angular_speed,
+            Action::PitchUp => pitch_velocity += self.angular_speed,
+            Action::PitchDown => pitch_velocity += -self.angular_speed,
+            _ => {}
+        });
+
+        // A screen pixel is two terminal columns wide, but only one row tall
+        let (drag_x, drag_y) = mouse_state.drag_delta;
+        self.yaw += yaw_velocity * delta_time + drag_x / 2.0 * self.mouse_sensitivity;
+        self.pitch += pitch_velocity * delta_time - drag_y * self.mouse_sensitivity;
+        self.pitch = self.pitch.clamp(-PI / 2.0, PI / 2.0);
+
+        // Zoom exponentially so each scroll step feels the same at any distance
+        self.distance *= (1.0 - self.zoom_speed).powf(mouse_state.scroll_delta);
+        self.distance = self.distance.clamp(self.min_distance, self.max_distance);
+    }
+
+    fn get_position(&self) -> Point3<f64> {
+        self.target - super::get_direction(self.yaw, self.pitch) * self.distance
+    }
+
+    fn get_forward(&self) -> Vector3<f64> {
+        super::get_direction(self.yaw, self.pitch)
+    }
+
+    fn get_view_matrix(&self) -> Matrix4<f64> {
+        super::get_view_matrix(self.get_position(), self.yaw, self.pitch)
+    }
+
+    fn get_projection_matrix(&self) -> Matrix4<f64> {
+        self.projection.to_homogeneous()
+    }
+
+    fn set_aspect_ratio(&mut self, aspect_ratio: f64) {
+        self.projection.set_aspect(aspect_ratio);
+    }
+
+    fn to_json(&self) -> Option<serde_json::Value> {
+        Some(json!({
+            "type": "orbit_control",
+            "target": self.target.coords.as_slice(),
+            "distance": self.distance,
+            "yaw": self.yaw,
+            "pitch": self.pitch,
+            "fov": self.fov,
+            "near_plane": self.near_plane,
+            "far_plane": self.far_plane,
+            "angular_speed": self.angular_speed,
+            "mouse_sensitivity": self.mouse_sensitivity,
+            "zoom_speed": self.zoom_speed,
+            "min_distance": self.min_distance,
+            "max_distance": self.max_distance,
+        }))
+    }
+}
+
+pub struct OrbitControlPerspectiveCameraBuilder {
+    target: Point3<f64>,
+    distance: f64,
+    yaw: f64,
+    pitch: f64,
+    aspect_ratio: f64,
+    fov: f64,
+    near_plane: f64,
+    far_plane: f64,
+    angular_speed: f64,
+    mouse_sensitivity: f64,
+    zoom_speed: f64,
+    min_distance: f64,
+    max_distance: f64,
+}
+
+impl Default for OrbitControlPerspectiveCameraBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OrbitControlPerspectiveCameraBuilder {
+    pub fn new() -> Self {
+        Self {
+            target: Point3::new(0.0, 0.0, 0.0),
+            distance: 3.0,
+            yaw: -std::f64::consts::PI / 2.0,
+            pitch: -0.4,
+            fov: 1.0,
+            aspect_ratio: 1.6,
+            near_plane: 0.01,
+            far_plane: 1000.0,
+            angular_speed: 1.0,
+            mouse_sensitivity: 0.02,
+            zoom_speed: 0.1,
+            min_distance: 0.1,
+            max_distance: 100.0,
+        }
+    }
+
+    #[allow(unused)]
+    pub fn target(&mut self, target: Point3<f64>) -> &mut Self {
+        self.target = target;
+        self
+    }
+
+    #[allow(unused)]
+    pub fn distance(&mut self, distance: f64) -> &mut Self {
+        self.distance = distance;
+        self
+    }
+
+    #[allow(unused)]
+    pub fn yaw(&mut self, yaw: f64) -> &mut Self {
+        self.yaw = yaw;
+        self
+    }
+
+    #[allow(unused)]
+    pub fn pitch(&mut self, pitch: f64) -> &mut Self {
+        self.pitch = pitch;
+        self
+    }
+
+    #[allow(unused)]
+    pub fn aspect_ratio(&mut self, aspect_ratio: f64) -> &mut Self {
+        self.aspect_ratio = aspect_ratio;
+        self
+    }
+
+    #[allow(unused)]
+    pub fn fov(&mut self, fov: f64) -> &mut Self {
+        self.fov = fov;
+        self
+    }
+
+    #[allow(unused)]
+    pub fn near_plane(&mut self, near_plane: f64) -> &mut Self {
+        self.near_plane = near_plane;
+        self
+    }
+
+    #[allow(unused)]
+    pub fn far_plane(&mut self, far_plane: f64) -> &mut Self {
+        self.far_plane = far_plane;
+        self
+    }
+
+    #[allow(unused)]
+    pub fn angular_speed(&mut self, angular_speed: f64) -> &mut Self {
+        self.angular_speed = angular_speed;
+        self
+    }
+
+    #[allow(unused)]
+    pub fn mouse_sensitivity(&mut self, mouse_sensitivity: f64) -> &mut Self {
+        self.mouse_sensitivity = mouse_sensitivity;
+        self
+    }
+
+    #[allow(unused)]
+    pub fn zoom_speed(&mut self, zoom_speed: f64) -> &mut Self {
+        self.zoom_speed = zoom_speed;
+        self
+    }
+
+    #[allow(unused)]
+    pub fn min_distance(&mut self, min_distance: f64) -> &mut Self {
+        self.min_distance = min_distance;
+        self
+    }
+
+    #[allow(unused)]
+    pub fn max_distance(&mut self, max_distance: f64) -> &mut Self {
+        self.max_distance = max_distance;
+        self
+    }
+
+    #[allow(unused)]
+    pub fn build(&mut self) -> OrbitControlPerspectiveCamera {
+        OrbitControlPerspectiveCamera::new(
+            self.target,
+            self.distance,
+            self.yaw,
+            self.pitch,
+            self.aspect_ratio,
+            self.fov,
+            self.near_plane,
+            self.far_plane,
+            self.angular_speed,
+            self.mouse_sensitivity,
+            self.zoom_speed,
+            self.min_distance,
+            self.max_distance,
+        )
+    }
+}
@@ -2,16 +2,23 @@
 
 use std::f64::consts::PI;
 
-use nalgebra::{Matrix4, Perspective3, Point3};
+use nalgebra::{Matrix4, Perspective3, Point3, Vector3};
+use serde_json::json;
 
 use crate::camera::Camera;
+use crate::terminal::key_bindings::Action;
+use crate::terminal::mouse::MouseState;
 
 pub struct OrbitingPerspectiveCamera {
     origin: Point3<f64>,
     yaw: f64,
     pitch: f64,
     angular_speed: f64,
-    projection_matrix: Matrix4<f64>,
+    // Kept since the projection matrix only gives them back with rounding errors
+    fov: f64,
+    near_plane: f64,
+    far_plane: f64,
+    projection: Perspective3<f64>,
 }
 
 impl OrbitingPerspectiveCamera {
@@ -25,18 +32,22 @@ impl OrbitingPerspectiveCamera {
         far_plane: f64,
         angular_speed: f64,
     ) -> Self {
-        let projection_matrix =
-            Perspective3::new(aspect_ratio, fov, near_plane, far_plane).to_homogeneous();
+        let projection = Perspective3::new(aspect_ratio, fov, near_plane, far_plane);
         OrbitingPerspectiveCamera {
             origin,
             yaw,
             pitch,
             angular_speed,
-            projection_matrix,
+            fov,
+            near_plane,
+            far_plane,
+            projection,
         }
     }
+}
 
-    pub fn update(&mut self, delta_time: f64) {
+impl Camera for OrbitingPerspectiveCamera {
+    fn update(&mut self, delta_time: f64, _actions: &[Action], _mouse_state: &MouseState) {
         self.yaw += self.angular_speed * delta_time / 4.0;
         let d = (self.origin.x * self.origin.x + self.origin.z * self.origin.z).sqrt();
         self.origin = Point3::new(
@@ -45,16 +56,38 @@ impl OrbitingPerspectiveCamera {
             d * (self.yaw + PI).sin(),
         );
     }
-}
 
-impl Camera for OrbitingPerspectiveCamera {
-    fn get_view_projection_matrix(&self) -> Matrix4<f64> {
-        super::get_view_projection_matrix(
-            &self.projection_matrix,
-            self.origin,
-            self.yaw,
-            self.pitch,
-        )
+    fn get_position(&self) -> Point3<f64> {
+        self.origin
+    }
+
+    fn get_forward(&self) -> Vector3<f64> {
+        super::get_direction(self.yaw, self.pitch)
+    }
+
+    fn get_view_matrix(&self) -> Matrix4<f64> {
+        super::get_view_matrix(self.get_position(), self.yaw, self.pitch)
+    }
+
+    fn get_projection_matrix(&self) -> Matrix4<f64> {
+        self.projection.to_homogeneous()
+    }
+
+    fn set_aspect_ratio(&mut self, aspect_ratio: f64) {
+        self.projection.set_aspect(aspect_ratio);
+    }
+
+    fn to_json(&self) -> Option<serde_json::Value> {
+        Some(json!({
+            "type": "orbiting",
+            "position": self.origin.coords.as_slice(),
+            "yaw": self.yaw,
+            "pitch": self.pitch,
+            "fov": self.fov,
+            "near_plane": self.near_plane,
+            "far_plane": self.far_plane,
+            "angular_speed": self.angular_speed,
+        }))
     }
 }
 
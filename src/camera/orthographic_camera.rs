@@ -0,0 +1,337 @@
+#![allow(clippy::too_many_arguments)]
+
+use std::f64::consts::PI;
+
+use nalgebra::{Matrix4, Orthographic3, Point3, Vector3};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::camera::Camera;
+use crate::terminal::key_bindings::Action;
+use crate::terminal::mouse::MouseState;
+
+const TOP_VIEW_PITCH: f64 = -PI / 2.0;
+
+#[derive(Copy, Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OrthographicPreset {
+    Isometric,
+    Top,
+    Front,
+    Side,
+}
+
+impl OrthographicPreset {
+    // Yaw and pitch of the view direction for the preset
+    pub fn get_yaw_pitch(&self) -> (f64, f64) {
+        match self {
+            OrthographicPreset::Isometric => (-3.0 * PI / 4.0, -(1.0 / 2.0_f64.sqrt()).atan()),
+            OrthographicPreset::Top => (-PI / 2.0, TOP_VIEW_PITCH),
+            OrthographicPreset::Front => (-PI / 2.0, 0.0),
+            OrthographicPreset::Side => (PI, 0.0),
+        }
+    }
+
+    pub fn next(&self) -> Self {
+        match self {
+            OrthographicPreset::Isometric => OrthographicPreset::Top,
+            OrthographicPreset::Top => OrthographicPreset::Front,
+            OrthographicPreset::Front => OrthographicPreset::Side,
+            OrthographicPreset::Side => OrthographicPreset::Isometric,
+        }
+    }
+}
+
+/*
+    Orthographic3 produces a box shaped view volume identical to glOrtho. The view volume
+    is view_height tall and view_height * aspect_ratio wide, centered on the view direction.
+    Dragging the mouse rotates the camera around the target, scrolling scales the view
+    volume, and the next view preset action cycles between the isometric, top, front and
+    side views of the target.
+*/
+pub struct OrthographicCamera {
+    origin: Point3<f64>,
+    target: Point3<f64>,
+    yaw: f64,
+    pitch: f64,
+    aspect_ratio: f64,
+    view_height: f64,
+    near_plane: f64,
+    far_plane: f64,
+    mouse_sensitivity: f64,
+    zoom_speed: f64,
+    preset: Option<OrthographicPreset>,
+    projection: Orthographic3<f64>,
+}
+
+impl OrthographicCamera {
+    pub fn new(
+        origin: Point3<f64>,
+        target: Point3<f64>,
+        yaw: f64,
+        pitch: f64,
+        aspect_ratio: f64,
+        view_height: f64,
+        near_plane: f64,
+        far_plane: f64,
+        mouse_sensitivity: f64,
+        zoom_speed: f64,
+        preset: Option<OrthographicPreset>,
+    ) -> Self {
+        let mut camera = OrthographicCamera {
+            origin,
+            target,
+            yaw,
+            pitch,
+            aspect_ratio,
+            view_height,
+            near_plane,
+            far_plane,
+            mouse_sensitivity,
+            zoom_speed,
+            preset: None,
+            projection: get_projection(aspect_ratio, view_height, near_plane, far_plane),
+        };
+        if let Some(preset) = preset {
+            camera.set_preset(preset);
+        }
+        camera
+    }
+
+    // Moves the camera to view the target from the preset direction, keeping its
+    // current distance from the target
+    pub fn set_preset(&mut self, preset: OrthographicPreset) {
+        let (yaw, pitch) = preset.get_yaw_pitch();
+        self.yaw = yaw;
+        self.pitch = pitch;
+        self.preset = Some(preset);
+        self.update_origin();
+    }
+
+    pub fn get_preset(&self) -> Option<OrthographicPreset> {
+        self.preset
+    }
+
+    fn update_origin(&mut self) {
+        let distance = (self.origin - self.target).magnitude();
+        self.origin = self.target - super::get_direction(self.yaw, self.pitch) * distance;
+    }
+
+    fn update_projection(&mut self) {
+        self.projection = get_projection(
+            self.aspect_ratio,
+            self.view_height,
+            self.near_plane,
+            self.far_plane,
+        );
+    }
+}
+
+impl Camera for OrthographicCamera {
+    fn update(&mut self, _delta_time: f64, _actions: &[Action], mouse_state: &MouseState) {
+        // A screen pixel is two terminal columns wide, but only one row tall
+        let (drag_x, drag_y) = mouse_state.drag_delta;
+        if drag_x != 0.0 || drag_y != 0.0 {
+            self.yaw += drag_x / 2.0 * self.mouse_sensitivity;
+            self.pitch -= drag_y * self.mouse_sensitivity;
+            self.pitch = self.pitch.clamp(TOP_VIEW_PITCH, -TOP_VIEW_PITCH);
+            self.preset = None;
+            self.update_origin();
+        }
+
+        if mouse_state.scroll_delta != 0.0 {
+            self.view_height *= (1.0 - self.zoom_speed).powf(mouse_state.scroll_delta);
+            self.update_projection();
+        }
+    }
+
+    fn trigger_action(&mut self, action: Action) {
+        if action == Action::NextViewPreset {
+            let preset = self
+                .preset
+                .map(|preset| preset.next())
+                .unwrap_or(OrthographicPreset::Isometric);
+            self.set_preset(preset);
+        }
+    }
+
+    fn get_position(&self) -> Point3<f64> {
+        self.origin
+    }
+
+    fn get_forward(&self) -> Vector3<f64> {
+        super::get_direction(self.yaw, self.pitch)
+    }
+
+    fn get_view_matrix(&self) -> Matrix4<f64> {
+        super::get_view_matrix(self.origin, self.yaw, self.pitch)
+    }
+
+    fn get_projection_matrix(&self) -> Matrix4<f64> {
+        self.projection.to_homogeneous()
+    }
+
+    fn set_aspect_ratio(&mut self, aspect_ratio: f64) {
+        self.aspect_ratio = aspect_ratio;
+        self.update_projection();
+    }
+
+    fn to_json(&self) -> Option<serde_json::Value> {
+        let mut json = json!({
+            "type": "orthographic",
+            "position": self.origin.coords.as_slice(),
+            "target": self.target.coords.as_slice(),
+            "yaw": self.yaw,
+            "pitch": self.pitch,
+            "view_height": self.view_height,
+            "near_plane": self.near_plane,
+            "far_plane": self.far_plane,
+            "mouse_sensitivity": self.mouse_sensitivity,
+            "zoom_speed": self.zoom_speed,
+        });
+        if let Some(preset) = self.preset {
+            json["preset"] = serde_json::to_value(preset).ok()?;
+        }
+        Some(json)
+    }
+}
+
+fn get_projection(
+    aspect_ratio: f64,
+    view_height: f64,
+    near_plane: f64,
+    far_plane: f64,
+) -> Orthographic3<f64> {
+    let half_height = view_height / 2.0;
+    let half_width = half_height * aspect_ratio;
+    Orthographic3::new(
+        -half_width,
+        half_width,
+        -half_height,
+        half_height,
+        near_plane,
+        far_plane,
+    )
+}
+
+pub struct OrthographicCameraBuilder {
+    origin: Point3<f64>,
+    target: Point3<f64>,
+    yaw: f64,
+    pitch: f64,
+    aspect_ratio: f64,
+    view_height: f64,
+    near_plane: f64,
+    far_plane: f64,
+    mouse_sensitivity: f64,
+    zoom_speed: f64,
+    preset: Option<OrthographicPreset>,
+}
+
+impl Default for OrthographicCameraBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OrthographicCameraBuilder {
+    pub fn new() -> Self {
+        Self {
+            origin: Point3::new(0.0, 0.0, 10.0),
+            target: Point3::new(0.0, 0.0, 0.0),
+            yaw: -std::f64::consts::PI / 2.0,
+            pitch: 0.0,
+            aspect_ratio: 1.6,
+            view_height: 3.0,
+            near_plane: 0.1,
+            far_plane: 100.0,
+            mouse_sensitivity: 0.02,
+            zoom_speed: 0.1,
+            preset: None,
+        }
+    }
+
+    #[allow(unused)]
+    pub fn origin(&mut self, origin: Point3<f64>) -> &mut Self {
+        self.origin = origin;
+        self
+    }
+
+    #[allow(unused)]
+    pub fn target(&mut self, target: Point3<f64>) -> &mut Self {
+        self.target = target;
+        self
+    }
+
+    #[allow(unused)]
+    pub fn yaw(&mut self, yaw: f64) -> &mut Self {
+        self.yaw = yaw;
+        self
+    }
+
+    #[allow(unused)]
+    pub fn pitch(&mut self, pitch: f64) -> &mut Self {
+        self.pitch = pitch;
+        self
+    }
+
+    #[allow(unused)]
+    pub fn aspect_ratio(&mut self, aspect_ratio: f64) -> &mut Self {
+        self.aspect_ratio = aspect_ratio;
+        self
+    }
+
+    #[allow(unused)]
+    pub fn view_height(&mut self, view_height: f64) -> &mut Self {
+        self.view_height = view_height;
+        self
+    }
+
+    #[allow(unused)]
+    pub fn near_plane(&mut self, near_plane: f64) -> &mut Self {
+        self.near_plane = near_plane;
+        self
+    }
+
+    #[allow(unused)]
+    pub fn far_plane(&mut self, far_plane: f64) -> &mut Self {
+        self.far_plane = far_plane;
+        self
+    }
+
+    #[allow(unused)]
+    pub fn mouse_sensitivity(&mut self, mouse_sensitivity: f64) -> &mut Self {
+        self.mouse_sensitivity = mouse_sensitivity;
+        self
+    }
+
+    #[allow(unused)]
+    pub fn zoom_speed(&mut self, zoom_speed: f64) -> &mut Self {
+        self.zoom_speed = zoom_speed;
+        self
+    }
+
+    // Overrides the yaw and pitch, placing the camera origin so it views the target
+    #[allow(unused)]
+    pub fn preset(&mut self, preset: OrthographicPreset) -> &mut Self {
+        self.preset = Some(preset);
+        self
+    }
+
+    #[allow(unused)]
+    pub fn build(&mut self) -> OrthographicCamera {
+        OrthographicCamera::new(
+            self.origin,
+            self.target,
+            self.yaw,
+            self.pitch,
+            self.aspect_ratio,
+            self.view_height,
+            self.near_plane,
+            self.far_plane,
+            self.mouse_sensitivity,
+            self.zoom_speed,
+            self.preset,
+        )
+    }
+}
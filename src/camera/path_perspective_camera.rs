@@ -0,0 +1,284 @@
+#![allow(clippy::too_many_arguments)]
+
+use nalgebra::{Matrix4, Perspective3, Point3, Vector3};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::camera::{Camera, Pose};
+use crate::interpolation::{self, Easing, PlaybackMode};
+use crate::terminal::key_bindings::Action;
+use crate::terminal::mouse::MouseState;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct CameraKeyframe {
+    pub time: f64,
+    pub position: Point3<f64>,
+    pub target: Point3<f64>,
+    pub fov: f64,
+    // Bank angle in radians, clockwise around the view direction
+    pub roll: f64,
+}
+
+#[derive(Copy, Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PathInterpolation {
+    Linear,
+    #[default]
+    CatmullRom,
+}
+
+/*
+    Camera following a scripted path through keyframes, for authoring flythroughs.
+    Positions and look at targets are interpolated between keyframes, with the easing
+    applied to the progress through each pair of keyframes.
+*/
+pub struct PathPerspectiveCamera {
+    keyframes: Vec<CameraKeyframe>,
+    interpolation: PathInterpolation,
+    easing: Easing,
+    playback_mode: PlaybackMode,
+    time: f64,
+    position: Point3<f64>,
+    target: Point3<f64>,
+    roll: f64,
+    near_plane: f64,
+    far_plane: f64,
+    projection: Perspective3<f64>,
+}
+
+impl PathPerspectiveCamera {
+    pub fn new(
+        keyframes: Vec<CameraKeyframe>,
+        interpolation: PathInterpolation,
+        easing: Easing,
+        playback_mode: PlaybackMode,
+        aspect_ratio: f64,
+        near_plane: f64,
+        far_plane: f64,
+    ) -> Self {
+        assert!(!keyframes.is_empty(), "Camera path requires a keyframe");
+
+        let mut keyframes = keyframes;
+        keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+
+        let first = keyframes[0];
+        let mut camera = PathPerspectiveCamera {
+            keyframes,
+            interpolation,
+            easing,
+            playback_mode,
+            time: 0.0,
+            position: first.position,
+            target: first.target,
+            roll: first.roll,
+            near_plane,
+            far_plane,
+            projection: Perspective3::new(aspect_ratio, first.fov, near_plane, far_plane),
+        };
+        camera.evaluate();
+        camera
+    }
+
+    pub fn get_duration(&self) -> f64 {
+        self.keyframes
+            .last()
+            .map(|keyframe| keyframe.time)
+            .unwrap_or(0.0)
+    }
+
+    pub fn set_time(&mut self, time: f64) {
+        self.time = time;
+        self.evaluate();
+    }
+
+    fn evaluate(&mut self) {
+        let time = self.playback_mode.wrap_time(self.time, self.get_duration());
+        let times: Vec<f64> = self
+            .keyframes
+            .iter()
+            .map(|keyframe| keyframe.time)
+            .collect();
+        let (index, t) = interpolation::find_segment(&times, time);
+        let t = self.easing.apply(t);
+
+        let last = self.keyframes.len() - 1;
+        let k0 = &self.keyframes[index.saturating_sub(1)];
+        let k1 = &self.keyframes[index];
+        let k2 = &self.keyframes[(index + 1).min(last)];
+        let k3 = &self.keyframes[(index + 2).min(last)];
+
+        let interpolate =
+            |a: Vector3<f64>, b: Vector3<f64>, c: Vector3<f64>, d: Vector3<f64>| match self
+                .interpolation
+            {
+                PathInterpolation::Linear => interpolation::lerp(b, c, t),
+                PathInterpolation::CatmullRom => interpolation::catmull_rom(a, b, c, d, t),
+            };
+
+        self.position = Point3::from(interpolate(
+            k0.position.coords,
+            k1.position.coords,
+            k2.position.coords,
+            k3.position.coords,
+        ));
+        self.target = Point3::from(interpolate(
+            k0.target.coords,
+            k1.target.coords,
+            k2.target.coords,
+            k3.target.coords,
+        ));
+        self.roll = interpolation::lerp(k1.roll, k2.roll, t);
+        self.projection
+            .set_fovy(interpolation::lerp(k1.fov, k2.fov, t));
+    }
+}
+
+impl Camera for PathPerspectiveCamera {
+    fn update(&mut self, delta_time: f64, _actions: &[Action], _mouse_state: &MouseState) {
+        self.time += delta_time;
+        self.evaluate();
+    }
+
+    fn get_position(&self) -> Point3<f64> {
+        self.position
+    }
+
+    // Taken from the pose, which stays defined where the path passes through its target
+    fn get_forward(&self) -> Vector3<f64> {
+        self.get_pose().get_forward()
+    }
+
+    fn get_view_matrix(&self) -> Matrix4<f64> {
+        self.get_pose().get_view_matrix()
+    }
+
+    fn get_projection_matrix(&self) -> Matrix4<f64> {
+        self.projection.to_homogeneous()
+    }
+
+    fn set_aspect_ratio(&mut self, aspect_ratio: f64) {
+        self.projection.set_aspect(aspect_ratio);
+    }
+
+    fn get_pose(&self) -> Pose {
+        let mut pose = Pose::look_at(self.position, self.target, &Vector3::y());
+        pose.rotate_local(0.0, 0.0, self.roll);
+        pose
+    }
+
+    fn to_json(&self) -> Option<serde_json::Value> {
+        let keyframes: Vec<serde_json::Value> = self
+            .keyframes
+            .iter()
+            .map(|keyframe| {
+                json!({
+                    "time": keyframe.time,
+                    "position": keyframe.position.coords.as_slice(),
+                    "target": keyframe.target.coords.as_slice(),
+                    "fov": keyframe.fov,
+                    "roll": keyframe.roll,
+                })
+            })
+            .collect();
+        Some(json!({
+            "type": "path",
+            "interpolation": self.interpolation,
+            "easing": self.easing,
+            "playback": self.playback_mode,
+            "near_plane": self.near_plane,
+            "far_plane": self.far_plane,
+            "keyframes": keyframes,
+        }))
+    }
+}
+
+pub struct PathPerspectiveCameraBuilder {
+    keyframes: Vec<CameraKeyframe>,
+    interpolation: PathInterpolation,
+    easing: Easing,
+    playback_mode: PlaybackMode,
+    aspect_ratio: f64,
+    near_plane: f64,
+    far_plane: f64,
+}
+
+impl Default for PathPerspectiveCameraBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PathPerspectiveCameraBuilder {
+    pub fn new() -> Self {
+        Self {
+            keyframes: Vec::new(),
+            interpolation: PathInterpolation::CatmullRom,
+            easing: Easing::Linear,
+            playback_mode: PlaybackMode::Loop,
+            aspect_ratio: 1.6,
+            near_plane: 0.01,
+            far_plane: 1000.0,
+        }
+    }
+
+    #[allow(unused)]
+    pub fn keyframe(&mut self, keyframe: CameraKeyframe) -> &mut Self {
+        self.keyframes.push(keyframe);
+        self
+    }
+
+    #[allow(unused)]
+    pub fn keyframes(&mut self, keyframes: Vec<CameraKeyframe>) -> &mut Self {
+        self.keyframes = keyframes;
+        self
+    }
+
+    #[allow(unused)]
+    pub fn interpolation(&mut self, interpolation: PathInterpolation) -> &mut Self {
+        self.interpolation = interpolation;
+        self
+    }
+
+    #[allow(unused)]
+    pub fn easing(&mut self, easing: Easing) -> &mut Self {
+        self.easing = easing;
+        self
+    }
+
+    #[allow(unused)]
+    pub fn playback_mode(&mut self, playback_mode: PlaybackMode) -> &mut Self {
+        self.playback_mode = playback_mode;
+        self
+    }
+
+    #[allow(unused)]
+    pub fn aspect_ratio(&mut self, aspect_ratio: f64) -> &mut Self {
+        self.aspect_ratio = aspect_ratio;
+        self
+    }
+
+    #[allow(unused)]
+    pub fn near_plane(&mut self, near_plane: f64) -> &mut Self {
+        self.near_plane = near_plane;
+        self
+    }
+
+    #[allow(unused)]
+    pub fn far_plane(&mut self, far_plane: f64) -> &mut Self {
+        self.far_plane = far_plane;
+        self
+    }
+
+    #[allow(unused)]
+    pub fn build(&mut self) -> PathPerspectiveCamera {
+        PathPerspectiveCamera::new(
+            self.keyframes.clone(),
+            self.interpolation,
+            self.easing,
+            self.playback_mode,
+            self.aspect_ratio,
+            self.near_plane,
+            self.far_plane,
+        )
+    }
+}
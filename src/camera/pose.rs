@@ -0,0 +1,80 @@
+use nalgebra::{Isometry3, Matrix4, Point3, Translation3, Unit, UnitQuaternion, Vector3};
+
+/*
+    Position and orientation of a camera. The orientation rotates the camera's local axes
+    into world space, where the camera looks down its local -z axis with +y up and +x to
+    the right, matching the OpenGL view space convention. Unlike a yaw / pitch pair the
+    quaternion can represent any orientation, including rolled and straight up or down.
+*/
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Pose {
+    pub position: Point3<f64>,
+    pub orientation: UnitQuaternion<f64>,
+}
+
+impl Pose {
+    pub fn new(position: Point3<f64>, orientation: UnitQuaternion<f64>) -> Self {
+        Self {
+            position,
+            orientation,
+        }
+    }
+
+    // Yaw is measured from the +x axis towards +z, pitch upwards from the horizontal
+    // plane, and roll clockwise around the view direction
+    pub fn from_yaw_pitch_roll(position: Point3<f64>, yaw: f64, pitch: f64, roll: f64) -> Self {
+        let yaw_rotation =
+            UnitQuaternion::from_axis_angle(&Vector3::y_axis(), -yaw - std::f64::consts::PI / 2.0);
+        let pitch_rotation = UnitQuaternion::from_axis_angle(&Vector3::x_axis(), pitch);
+        let roll_rotation = UnitQuaternion::from_axis_angle(&Vector3::z_axis(), -roll);
+
+        Self::new(position, yaw_rotation * pitch_rotation * roll_rotation)
+    }
+
+    // Looks from the position towards the target. Falls back to another up vector when
+    // looking along the requested one, so the orientation is always well defined
+    pub fn look_at(position: Point3<f64>, target: Point3<f64>, up: &Vector3<f64>) -> Self {
+        let forward = target - position;
+        if forward.magnitude_squared() == 0.0 {
+            return Self::new(position, UnitQuaternion::identity());
+        }
+
+        let up = if forward.cross(up).magnitude_squared() < 1e-12 {
+            Vector3::z()
+        } else {
+            *up
+        };
+
+        Self::new(position, UnitQuaternion::face_towards(&-forward, &up))
+    }
+
+    pub fn get_forward(&self) -> Vector3<f64> {
+        self.orientation * -Vector3::z()
+    }
+
+    pub fn get_up(&self) -> Vector3<f64> {
+        self.orientation * Vector3::y()
+    }
+
+    pub fn get_right(&self) -> Vector3<f64> {
+        self.orientation * Vector3::x()
+    }
+
+    // Rotates around the camera's own right, up and forward axes
+    pub fn rotate_local(&mut self, pitch: f64, yaw: f64, roll: f64) {
+        let rotation = UnitQuaternion::from_axis_angle(&Vector3::x_axis(), pitch)
+            * UnitQuaternion::from_axis_angle(&Vector3::y_axis(), -yaw)
+            * UnitQuaternion::from_axis_angle(&Vector3::z_axis(), -roll);
+        self.orientation *= rotation;
+    }
+
+    pub fn rotate_around(&mut self, axis: &Unit<Vector3<f64>>, angle: f64) {
+        self.orientation = UnitQuaternion::from_axis_angle(axis, angle) * self.orientation;
+    }
+
+    pub fn get_view_matrix(&self) -> Matrix4<f64> {
+        Isometry3::from_parts(Translation3::from(self.position.coords), self.orientation)
+            .inverse()
+            .to_homogeneous()
+    }
+}
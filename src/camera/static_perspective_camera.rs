@@ -1,12 +1,19 @@
-use nalgebra::{Matrix4, Perspective3, Point3};
+use nalgebra::{Matrix4, Perspective3, Point3, Vector3};
+use serde_json::json;
 
 use crate::camera::Camera;
+use crate::terminal::key_bindings::Action;
+use crate::terminal::mouse::MouseState;
 
 pub struct StaticPerspectiveCamera {
     origin: Point3<f64>,
     yaw: f64,
     pitch: f64,
-    projection_matrix: Matrix4<f64>,
+    // Kept since the projection matrix only gives them back with rounding errors
+    fov: f64,
+    near_plane: f64,
+    far_plane: f64,
+    projection: Perspective3<f64>,
 }
 
 /*
@@ -30,25 +37,52 @@ impl StaticPerspectiveCamera {
         near_plane: f64,
         far_plane: f64,
     ) -> Self {
-        let projection_matrix =
-            Perspective3::new(aspect_ratio, fov, near_plane, far_plane).to_homogeneous();
+        let projection = Perspective3::new(aspect_ratio, fov, near_plane, far_plane);
         StaticPerspectiveCamera {
             origin,
             yaw,
             pitch,
-            projection_matrix,
+            fov,
+            near_plane,
+            far_plane,
+            projection,
         }
     }
 }
 
 impl Camera for StaticPerspectiveCamera {
-    fn get_view_projection_matrix(&self) -> Matrix4<f64> {
-        super::get_view_projection_matrix(
-            &self.projection_matrix,
-            self.origin,
-            self.yaw,
-            self.pitch,
-        )
+    fn update(&mut self, _delta_time: f64, _actions: &[Action], _mouse_state: &MouseState) {}
+
+    fn get_position(&self) -> Point3<f64> {
+        self.origin
+    }
+
+    fn get_forward(&self) -> Vector3<f64> {
+        super::get_direction(self.yaw, self.pitch)
+    }
+
+    fn get_view_matrix(&self) -> Matrix4<f64> {
+        super::get_view_matrix(self.get_position(), self.yaw, self.pitch)
+    }
+
+    fn get_projection_matrix(&self) -> Matrix4<f64> {
+        self.projection.to_homogeneous()
+    }
+
+    fn set_aspect_ratio(&mut self, aspect_ratio: f64) {
+        self.projection.set_aspect(aspect_ratio);
+    }
+
+    fn to_json(&self) -> Option<serde_json::Value> {
+        Some(json!({
+            "type": "static",
+            "position": self.origin.coords.as_slice(),
+            "yaw": self.yaw,
+            "pitch": self.pitch,
+            "fov": self.fov,
+            "near_plane": self.near_plane,
+            "far_plane": self.far_plane,
+        }))
     }
 }
 
@@ -0,0 +1,252 @@
+use nalgebra::{Point3, Vector3};
+
+use crate::geometry::{self, Triangle3};
+
+// Surfaces whose normal points at least this far up can be stood on
+const MIN_GROUND_NORMAL_Y: f64 = 0.7;
+// How far below the feet the ground is searched for, so walking down slopes and steps
+// follows the ground instead of falling
+const GROUND_SNAP_DISTANCE: f64 = 0.1;
+// Ground up to this far above the feet is snapped onto, for walking up slopes
+const MAX_STEP_HEIGHT: f64 = 0.2;
+const COLLISION_ITERATIONS: usize = 2;
+// Falling further than this below the spawn point respawns the walker
+const MAX_FALL_DISTANCE: f64 = 100.0;
+
+/*
+    First person walking physics for a camera at the top of a collision capsule. The
+    capsule is eye_height tall with a radius of collision_radius, and is approximated by
+    a column of spheres pushed out of the world triangles. The camera falls under gravity
+    onto the scene geometry and snaps to the ground with a ray cast down from the eye.
+*/
+pub struct WalkController {
+    eye_height: f64,
+    collision_radius: f64,
+    gravity: f64,
+    jump_speed: f64,
+    vertical_velocity: f64,
+    is_grounded: bool,
+    spawn_point: Point3<f64>,
+    triangles: Vec<Triangle3>,
+}
+
+impl Default for WalkController {
+    fn default() -> Self {
+        Self::new(0.6, 0.15, 9.8, 3.0)
+    }
+}
+
+impl WalkController {
+    pub fn new(eye_height: f64, collision_radius: f64, gravity: f64, jump_speed: f64) -> Self {
+        WalkController {
+            eye_height,
+            collision_radius,
+            gravity,
+            jump_speed,
+            vertical_velocity: 0.0,
+            is_grounded: false,
+            spawn_point: Point3::origin(),
+            triangles: Vec::new(),
+        }
+    }
+
+    // Starts walking from the eye position, which is also where a fall respawns the walker
+    pub fn reset(&mut self, eye: Point3<f64>) {
+        self.spawn_point = eye;
+        self.vertical_velocity = 0.0;
+        self.is_grounded = false;
+    }
+
+    // World space triangles to collide with, as produced by the scene graph
+    pub fn set_triangles(&mut self, triangles: Vec<Triangle3>) {
+        self.triangles = triangles;
+    }
+
+    pub fn is_grounded(&self) -> bool {
+        self.is_grounded
+    }
+
+    // Moves the eye by the horizontal displacement, applying gravity, jumping and collisions
+    pub fn step(
+        &mut self,
+        eye: &mut Point3<f64>,
+        horizontal_displacement: Vector3<f64>,
+        jump: bool,
+        delta_time: f64,
+    ) {
+        if jump && self.is_grounded {
+            self.vertical_velocity = self.jump_speed;
+        }
+        self.vertical_velocity -= self.gravity * delta_time;
+
+        let displacement =
+            horizontal_displacement + Vector3::new(0.0, self.vertical_velocity * delta_time, 0.0);
+        let triangles = self.get_nearby_triangles(eye, &displacement);
+
+        // Sub steps keep fast movement from tunneling through thin walls
+        let step_count = ((displacement.magnitude() / (self.collision_radius * 0.5)).ceil()
+            as usize)
+            .clamp(1, 16);
+        let was_grounded = self.is_grounded;
+        self.is_grounded = false;
+        for _ in 0..step_count {
+            *eye += displacement / step_count as f64;
+            self.resolve_collisions(eye, &triangles);
+        }
+
+        self.snap_to_ground(eye, was_grounded, &triangles);
+
+        if eye.y < self.spawn_point.y - MAX_FALL_DISTANCE {
+            self.reset(self.spawn_point);
+            *eye = self.spawn_point;
+        }
+    }
+
+    /*
+        The triangles the capsule can touch while moving by the displacement, so the sub steps
+        and the ground snap don't each test every triangle in the scene. The bounds of the
+        movement are widened to leave room for collision pushes, stepping up and the snap.
+    */
+    fn get_nearby_triangles(
+        &self,
+        eye: &Point3<f64>,
+        displacement: &Vector3<f64>,
+    ) -> Vec<Triangle3> {
+        let margin = self.collision_radius * 2.0 + MAX_STEP_HEIGHT + GROUND_SNAP_DISTANCE;
+        let end = eye + displacement;
+        let min = Point3::new(
+            eye.x.min(end.x) - margin,
+            eye.y.min(end.y) - self.eye_height - margin,
+            eye.z.min(end.z) - margin,
+        );
+        let max = Point3::new(
+            eye.x.max(end.x) + margin,
+            eye.y.max(end.y) + margin,
+            eye.z.max(end.z) + margin,
+        );
+        self.triangles
+            .iter()
+            .filter(|triangle| overlaps_bounds(triangle, &min, &max))
+            .copied()
+            .collect()
+    }
+
+    fn get_sphere_centers(&self, eye: &Point3<f64>) -> Vec<Point3<f64>> {
+        let bottom = eye.y - self.eye_height + self.collision_radius;
+        let length = (eye.y - bottom).max(0.0);
+        let count = (length / self.collision_radius).ceil() as usize + 1;
+        (0..count)
+            .map(|i| {
+                let t = if count > 1 {
+                    i as f64 / (count - 1) as f64
+                } else {
+                    0.0
+                };
+                Point3::new(eye.x, bottom + length * t, eye.z)
+            })
+            .collect()
+    }
+
+    fn resolve_collisions(&mut self, eye: &mut Point3<f64>, triangles: &[Triangle3]) {
+        let radius = self.collision_radius;
+        for _ in 0..COLLISION_ITERATIONS {
+            let centers = self.get_sphere_centers(eye);
+            let min = Point3::new(eye.x - radius, centers[0].y - radius, eye.z - radius);
+            let max = Point3::new(eye.x + radius, eye.y + radius, eye.z + radius);
+
+            let mut push = Vector3::zeros();
+            for triangle in triangles {
+                if !overlaps_bounds(triangle, &min, &max) {
+                    continue;
+                }
+
+                for center in &centers {
+                    let offset = (center + push)
+                        - geometry::closest_point_on_triangle(&(center + push), triangle);
+                    let distance = offset.magnitude();
+                    if distance >= radius {
+                        continue;
+                    }
+
+                    let normal = if distance > 1e-9 {
+                        offset / distance
+                    } else {
+                        get_face_normal(triangle)
+                    };
+                    push += normal * (radius - distance);
+
+                    if normal.y > MIN_GROUND_NORMAL_Y {
+                        self.is_grounded = true;
+                        self.vertical_velocity = self.vertical_velocity.max(0.0);
+                    } else if normal.y < -MIN_GROUND_NORMAL_Y {
+                        // Bumped into a ceiling
+                        self.vertical_velocity = self.vertical_velocity.min(0.0);
+                    }
+                }
+            }
+
+            if push == Vector3::zeros() {
+                break;
+            }
+            *eye += push;
+        }
+    }
+
+    fn snap_to_ground(
+        &mut self,
+        eye: &mut Point3<f64>,
+        was_grounded: bool,
+        triangles: &[Triangle3],
+    ) {
+        if self.vertical_velocity > 0.0 {
+            return;
+        }
+
+        let snap_distance = if was_grounded || self.is_grounded {
+            GROUND_SNAP_DISTANCE
+        } else {
+            0.0
+        };
+        let min_distance = self.eye_height - MAX_STEP_HEIGHT;
+        let max_distance = self.eye_height + snap_distance;
+
+        let down = Vector3::new(0.0, -1.0, 0.0);
+        let ground_distance = triangles
+            .iter()
+            .filter(|triangle| get_face_normal(triangle).y.abs() > MIN_GROUND_NORMAL_Y)
+            .filter_map(|triangle| geometry::ray_triangle_intersection(eye, &down, triangle))
+            .filter(|distance| (min_distance..=max_distance).contains(distance))
+            .fold(f64::INFINITY, f64::min);
+
+        if ground_distance.is_finite() {
+            eye.y += self.eye_height - ground_distance;
+            self.vertical_velocity = 0.0;
+            self.is_grounded = true;
+        }
+    }
+}
+
+fn overlaps_bounds(triangle: &Triangle3, min: &Point3<f64>, max: &Point3<f64>) -> bool {
+    (0..3).all(|axis| {
+        let lowest = triangle
+            .vertices
+            .iter()
+            .map(|vertex| vertex[axis])
+            .fold(f64::INFINITY, f64::min);
+        let highest = triangle
+            .vertices
+            .iter()
+            .map(|vertex| vertex[axis])
+            .fold(f64::NEG_INFINITY, f64::max);
+        lowest <= max[axis] && highest >= min[axis]
+    })
+}
+
+// Model normals may be flipped or smoothed, so the winding of the vertices is used instead
+fn get_face_normal(triangle: &Triangle3) -> Vector3<f64> {
+    let (v0, v1, v2) = triangle.vertices();
+    (v1 - v0)
+        .cross(&(v2 - v0))
+        .try_normalize(1e-12)
+        .unwrap_or_else(Vector3::y)
+}
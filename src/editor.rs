@@ -0,0 +1,338 @@
+use nalgebra::{Matrix4, Rotation3, Vector3};
+
+use crate::camera::Camera;
+use crate::entity::Square;
+use crate::light::Light;
+use crate::models::ModelStore;
+use crate::scene::Scene;
+use crate::terminal::key_bindings::Action;
+
+// Distance in front of the camera that added objects are placed at
+const NEW_OBJECT_DISTANCE: f64 = 3.0;
+
+// Rates per second the held increase and decrease actions change the selection by
+const MOVE_SPEED: f64 = 1.0;
+const ROTATE_SPEED: f64 = 1.0;
+// Scales by a factor of e^SCALE_SPEED per second, so growing and shrinking take as long
+const SCALE_SPEED: f64 = 0.5;
+const INTENSITY_SPEED: f64 = 0.5;
+const ATTENUATION_SPEED: f64 = 0.2;
+
+const AXIS_NAMES: [&str; 3] = ["x", "y", "z"];
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Selection {
+    // Index in the scene graph
+    Entity(usize),
+    // Index in the scene's lights
+    Light(usize),
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum EditorTool {
+    Move,
+    Rotate,
+    Scale,
+    Intensity,
+    LinearAttenuation,
+    QuadraticAttenuation,
+}
+
+impl EditorTool {
+    fn get_name(&self) -> &'static str {
+        match self {
+            EditorTool::Move => "move",
+            EditorTool::Rotate => "rotate",
+            EditorTool::Scale => "scale",
+            EditorTool::Intensity => "intensity",
+            EditorTool::LinearAttenuation => "linear attenuation",
+            EditorTool::QuadraticAttenuation => "quadratic attenuation",
+        }
+    }
+
+    // Whether the tool changes the selection along the current axis
+    fn uses_axis(&self) -> bool {
+        matches!(self, EditorTool::Move | EditorTool::Rotate)
+    }
+}
+
+/*
+    Edits the scene while it runs. Entities and lights are selected by cycling through them or
+    by clicking them, then moved, rotated and scaled along an axis or have their light settings
+    changed with the increase and decrease actions. Objects are added in front of the camera
+    using any model in the model store, and the selection can be removed. The edits live in the
+    scene, so saving the scene keeps them.
+*/
+pub struct Editor {
+    is_active: bool,
+    selection: Option<Selection>,
+    tool: EditorTool,
+    axis: usize,
+    // Index in the model store's model names of the model added objects use
+    model_index: usize,
+}
+
+impl Default for Editor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Editor {
+    pub fn new() -> Self {
+        Editor {
+            is_active: false,
+            selection: None,
+            tool: EditorTool::Move,
+            axis: 0,
+            model_index: 0,
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.is_active
+    }
+
+    // The selection, if it still exists in the scene
+    pub fn get_selection(&self, scene: &Scene) -> Option<Selection> {
+        self.selection.filter(|selection| match selection {
+            Selection::Entity(index) => *index < scene.scene_graph.len(),
+            Selection::Light(index) => *index < scene.lights.len(),
+        })
+    }
+
+    // Selects the entity or light, switching to a tool that applies to it
+    pub fn select(&mut self, scene: &Scene, selection: Option<Selection>) {
+        self.selection = selection;
+        let tools = get_tools(scene, self.get_selection(scene));
+        if !tools.contains(&self.tool) {
+            self.tool = tools[0];
+        }
+    }
+
+    // Handles the one-shot editor actions, which are ignored while the editor is inactive
+    pub fn trigger_action<'a>(
+        &mut self,
+        action: Action,
+        scene: &mut Scene<'a>,
+        model_store: &'a ModelStore,
+        camera: &dyn Camera,
+    ) {
+        if action == Action::ToggleEditor {
+            self.is_active = !self.is_active;
+            return;
+        }
+        if !self.is_active {
+            return;
+        }
+
+        let selection = self.get_selection(scene);
+        match action {
+            Action::EditorNextSelection => self.cycle_selection(scene, 1),
+            Action::EditorPreviousSelection => self.cycle_selection(scene, -1),
+            Action::EditorNextTool => {
+                let tools = get_tools(scene, selection);
+                let tool_index = tools.iter().position(|tool| *tool == self.tool);
+                self.tool = tools[tool_index.map_or(0, |index| (index + 1) % tools.len())];
+            }
+            Action::EditorAxisX => self.axis = 0,
+            Action::EditorAxisY => self.axis = 1,
+            Action::EditorAxisZ => self.axis = 2,
+            Action::EditorNextModel => self.model_index = self.model_index.wrapping_add(1),
+            Action::EditorPreviousModel => self.model_index = self.model_index.wrapping_sub(1),
+            Action::EditorAddObject => {
+                let Some(model_name) = self.get_model_name(model_store) else {
+                    return;
+                };
+                let index = scene.scene_graph.add(
+                    Box::new(Square {
+                        id: None,
+                        model: model_store.get_model(model_name),
+                        origin: camera.get_position() + camera.get_forward() * NEW_OBJECT_DISTANCE,
+                        rotation: Rotation3::identity(),
+                        scale: Matrix4::identity(),
+                    }),
+                    None,
+                );
+                self.select(scene, Some(Selection::Entity(index)));
+            }
+            Action::EditorRemoveSelection => {
+                match selection {
+                    Some(Selection::Entity(index)) => scene.scene_graph.remove(index),
+                    Some(Selection::Light(index)) => {
+                        scene.lights.remove(index);
+                    }
+                    None => {}
+                }
+                self.select(scene, None);
+            }
+            _ => {}
+        }
+    }
+
+    // Applies the held increase and decrease actions to the selection
+    pub fn update(&mut self, delta_time: f64, actions: &[Action], scene: &mut Scene) {
+        if !self.is_active {
+            return;
+        }
+
+        let mut amount = 0.0;
+        if actions.contains(&Action::EditorIncrease) {
+            amount += delta_time;
+        }
+        if actions.contains(&Action::EditorDecrease) {
+            amount -= delta_time;
+        }
+        if amount == 0.0 {
+            return;
+        }
+
+        let axis = Vector3::ith(self.axis, 1.0);
+        match self.get_selection(scene) {
+            Some(Selection::Entity(index)) => {
+                let entity = scene.scene_graph.get_entity_mut(index);
+                let Some(mut transform) = entity.get_transform() else {
+                    return;
+                };
+                match self.tool {
+                    EditorTool::Move => transform.position += axis * amount * MOVE_SPEED,
+                    EditorTool::Rotate => {
+                        transform.rotation =
+                            Rotation3::new(axis * amount * ROTATE_SPEED) * transform.rotation
+                    }
+                    EditorTool::Scale => transform.scale *= (amount * SCALE_SPEED).exp(),
+                    _ => {}
+                }
+                entity.set_transform(transform);
+                scene.scene_graph.update_world_transforms();
+            }
+            Some(Selection::Light(index)) => match &mut scene.lights[index] {
+                Light::PointLight(point_light) => match self.tool {
+                    EditorTool::Move => point_light.origin += axis * amount * MOVE_SPEED,
+                    EditorTool::Intensity => {
+                        point_light.intensity =
+                            (point_light.intensity + amount * INTENSITY_SPEED).max(0.0)
+                    }
+                    EditorTool::LinearAttenuation => {
+                        point_light.linear_attenuation =
+                            (point_light.linear_attenuation + amount * ATTENUATION_SPEED).max(0.0)
+                    }
+                    EditorTool::QuadraticAttenuation => {
+                        point_light.quadratic_attenuation = (point_light.quadratic_attenuation
+                            + amount * ATTENUATION_SPEED)
+                            .max(0.0)
+                    }
+                    _ => {}
+                },
+                Light::AmbientLight(ambient_light) => {
+                    ambient_light.intensity =
+                        (ambient_light.intensity + amount * INTENSITY_SPEED).max(0.0)
+                }
+            },
+            None => {}
+        }
+    }
+
+    // One line describing the selection and the editor settings, to show in the HUD
+    pub fn get_status(&self, scene: &Scene, model_store: &ModelStore) -> String {
+        let selection = match self.get_selection(scene) {
+            Some(Selection::Entity(index)) => {
+                let entity = scene.scene_graph.get_entity(index);
+                let name = entity
+                    .get_id()
+                    .map_or_else(|| format!("#{}", index), |id| id.to_string());
+                let type_name = entity
+                    .to_json()
+                    .and_then(|json| Some(json["type"].as_str()?.to_string()))
+                    .unwrap_or_else(|| "custom".to_string());
+                match entity.get_transform() {
+                    Some(transform) => format!(
+                        "{} [{}] at ({:.2}, {:.2}, {:.2}) scale ({:.2}, {:.2}, {:.2})",
+                        name,
+                        type_name,
+                        transform.position.x,
+                        transform.position.y,
+                        transform.position.z,
+                        transform.scale.x,
+                        transform.scale.y,
+                        transform.scale.z,
+                    ),
+                    None => format!("{} [{}] can't be edited", name, type_name),
+                }
+            }
+            Some(Selection::Light(index)) => match &scene.lights[index] {
+                Light::PointLight(point_light) => format!(
+                    "light {} [PointLight] at ({:.2}, {:.2}, {:.2}) intensity {:.2} attenuation {:.2} {:.2}",
+                    index,
+                    point_light.origin.x,
+                    point_light.origin.y,
+                    point_light.origin.z,
+                    point_light.intensity,
+                    point_light.linear_attenuation,
+                    point_light.quadratic_attenuation,
+                ),
+                Light::AmbientLight(ambient_light) => format!(
+                    "light {} [AmbientLight] intensity {:.2}",
+                    index, ambient_light.intensity
+                ),
+            },
+            None => "nothing selected".to_string(),
+        };
+
+        let tool = if self.tool.uses_axis() {
+            format!("{} {}", self.tool.get_name(), AXIS_NAMES[self.axis])
+        } else {
+            self.tool.get_name().to_string()
+        };
+        let model_name = self.get_model_name(model_store).unwrap_or("no models");
+        format!("Editor: {} | {} | add {}", selection, tool, model_name)
+    }
+
+    fn get_model_name<'a>(&self, model_store: &'a ModelStore) -> Option<&'a str> {
+        let model_names = model_store.get_model_names();
+        if model_names.is_empty() {
+            return None;
+        }
+        Some(model_names[self.model_index % model_names.len()])
+    }
+
+    // Steps through the entities followed by the lights, wrapping around at either end
+    fn cycle_selection(&mut self, scene: &Scene, step: isize) {
+        let entity_count = scene.scene_graph.len();
+        let selection_count = entity_count + scene.lights.len();
+        if selection_count == 0 {
+            return;
+        }
+
+        let next_index = match self.get_selection(scene) {
+            Some(Selection::Entity(index)) => index as isize + step,
+            Some(Selection::Light(index)) => (entity_count + index) as isize + step,
+            None if step > 0 => 0,
+            None => -1,
+        }
+        .rem_euclid(selection_count as isize) as usize;
+
+        let selection = if next_index < entity_count {
+            Selection::Entity(next_index)
+        } else {
+            Selection::Light(next_index - entity_count)
+        };
+        self.select(scene, Some(selection));
+    }
+}
+
+// The tools that apply to the selection, in the order the next tool action cycles through
+fn get_tools(scene: &Scene, selection: Option<Selection>) -> &'static [EditorTool] {
+    match selection {
+        Some(Selection::Light(index)) => match scene.lights[index] {
+            Light::PointLight(_) => &[
+                EditorTool::Move,
+                EditorTool::Intensity,
+                EditorTool::LinearAttenuation,
+                EditorTool::QuadraticAttenuation,
+            ],
+            Light::AmbientLight(_) => &[EditorTool::Intensity],
+        },
+        _ => &[EditorTool::Move, EditorTool::Rotate, EditorTool::Scale],
+    }
+}
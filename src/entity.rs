@@ -1,92 +1,185 @@
-use crate::geometry::{Color, Model, Triangle3};
+use crate::geometry::{Color, Model};
 use nalgebra::{Matrix4, Point3, Rotation3, Unit, Vector3};
+use serde_json::json;
 
-pub enum Entity<'a> {
-    Square(Square<'a>),
-    SpinningObject(SpinningObject<'a>),
-    Rectangle(Rectangle<'a>),
-}
+mod registry;
+pub use registry::parse_entity_json;
+pub use registry::EntityDescription;
+pub use registry::EntityFactory;
+pub use registry::EntityRegistry;
 
-impl<'a> Entity<'a> {
-    pub fn update(&mut self, dt: f64) {
-        if let Entity::SpinningObject(object) = self {
-            object.update(dt)
-        }
+/*
+    Anything that can be placed in a scene and rendered. The model geometry is transformed
+    into its parent's space by scale, then rotation, then translation to the origin. Library users
+    can implement this for their own entities and register a factory for them with the
+    EntityRegistry so scene files can create them by type name.
+*/
+pub trait Entity {
+    // Advances any animation by the frame time, called once per frame unless paused
+    fn update(&mut self, _delta_time: f64) {}
+
+    // Camera position in the entity's parent space, for entities that turn to face it
+    fn set_view_position(&mut self, _view_position: Point3<f64>) {}
+
+    // Unique name given to the entity in the scene file, used to look it up
+    fn get_id(&self) -> Option<&str> {
+        None
     }
 
-    pub fn get_origin(&self) -> Point3<f64> {
-        match self {
-            Entity::Square(square) => square.origin,
-            Entity::SpinningObject(object) => object.origin,
-            Entity::Rectangle(rectangle) => rectangle.origin,
-        }
+    fn get_origin(&self) -> Point3<f64>;
+
+    fn get_rotation(&self) -> Rotation3<f64>;
+
+    fn get_scale(&self) -> Matrix4<f64>;
+
+    fn get_model(&self) -> &Model;
+
+    // The transform given in the scene file, before any spin, animation or behavior is applied.
+    // Entities that can't be moved by an editor return None
+    fn get_transform(&self) -> Option<Transform> {
+        None
     }
 
-    pub fn get_rotation(&self) -> Rotation3<f64> {
-        match self {
-            Entity::Square(square) => square.rotation,
-            Entity::SpinningObject(object) => {
-                let axis_rotation = Rotation3::from_axis_angle(
-                    &Unit::new_normalize(Vector3::new(
-                        object.rotation_axis[0],
-                        object.rotation_axis[1],
-                        object.rotation_axis[2],
-                    )),
-                    object.rotation_angle,
-                );
-
-                return axis_rotation * object.rotation;
-            }
-            Entity::Rectangle(rectangle) => rectangle.rotation,
-        }
+    // Replaces the transform given in the scene file, for entities returning it
+    fn set_transform(&mut self, _transform: Transform) {}
+
+    // Transform from the model's space to its parent's space in the scene graph
+    fn get_local_transform(&self) -> Matrix4<f64> {
+        Matrix4::new_translation(&self.get_origin().coords)
+            * self.get_rotation().to_homogeneous()
+            * self.get_scale()
     }
 
-    pub fn get_scale(&self) -> Matrix4<f64> {
-        match self {
-            Entity::Square(square) => square.scale,
-            Entity::SpinningObject(object) => object.scale,
-            Entity::Rectangle(rectangle) => rectangle.scale,
-        }
+    // Overrides the color of every triangle in the model
+    fn get_maybe_color(&self) -> Option<Color> {
+        None
+    }
+
+    // The object describing the entity in a scene file, so the scene can be saved. Entities
+    // that can't be described return None
+    fn to_json(&self) -> Option<serde_json::Value> {
+        None
     }
+}
+
+// Placement of an object relative to its parent, as given in the scene file
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Transform {
+    pub position: Point3<f64>,
+    pub rotation: Rotation3<f64>,
+    pub scale: Vector3<f64>,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
-    pub fn get_model_geometry(&self) -> Vec<Triangle3> {
-        match self {
-            Entity::Square(square) => square.model.geometry.clone(),
-            Entity::SpinningObject(object) => object.model.geometry.clone(),
-            Entity::Rectangle(rectangle) => rectangle.model.geometry.clone(),
+impl Transform {
+    pub fn new() -> Self {
+        Transform {
+            position: Point3::origin(),
+            rotation: Rotation3::identity(),
+            scale: Vector3::new(1.0, 1.0, 1.0),
         }
     }
 
-    pub fn get_maybe_color(&self) -> Option<Color> {
-        match self {
-            Entity::Rectangle(rectangle) => Some(rectangle.color),
-            _ => None,
+    pub fn get_scale_matrix(&self) -> Matrix4<f64> {
+        Matrix4::new_nonuniform_scaling(&self.scale)
+    }
+
+    // The inverse of get_scale_matrix, for entities storing their scale as a matrix
+    pub fn from_scale_matrix(
+        position: Point3<f64>,
+        rotation: Rotation3<f64>,
+        scale: &Matrix4<f64>,
+    ) -> Self {
+        Transform {
+            position,
+            rotation,
+            scale: Vector3::new(scale[(0, 0)], scale[(1, 1)], scale[(2, 2)]),
         }
     }
 }
 
-trait Updatable {
-    fn update(&mut self, dt: f64);
+// Checks a rotation axis from a scene file. Zero or non-finite axes can't be normalized, and
+// would move the entity to NaN
+pub fn validate_axis(name: &str, axis: &[f64; 3]) -> Result<(), String> {
+    // Also false for NaN, infinite and underflowing lengths
+    if Vector3::from(*axis).magnitude_squared().is_normal() {
+        Ok(())
+    } else {
+        Err(format!(
+            "{} must be a finite, non-zero vector, got {:?}",
+            name, axis
+        ))
+    }
 }
 
-trait WorldLight {}
-
 #[derive(Copy, Clone)]
 pub struct Cube {
     pub origin: Point3<f64>,
     pub rotation: Rotation3<f64>,
 }
 
-#[derive(Copy, Clone)]
+#[derive(Clone)]
 pub struct Square<'a> {
+    pub id: Option<String>,
     pub model: &'a Model,
     pub origin: Point3<f64>,
     pub rotation: Rotation3<f64>,
     pub scale: Matrix4<f64>,
 }
 
-#[derive(Copy, Clone)]
+impl Entity for Square<'_> {
+    fn get_id(&self) -> Option<&str> {
+        self.id.as_deref()
+    }
+
+    fn get_origin(&self) -> Point3<f64> {
+        self.origin
+    }
+
+    fn get_rotation(&self) -> Rotation3<f64> {
+        self.rotation
+    }
+
+    fn get_scale(&self) -> Matrix4<f64> {
+        self.scale
+    }
+
+    fn get_model(&self) -> &Model {
+        self.model
+    }
+
+    fn get_transform(&self) -> Option<Transform> {
+        Some(Transform::from_scale_matrix(
+            self.origin,
+            self.rotation,
+            &self.scale,
+        ))
+    }
+
+    fn set_transform(&mut self, transform: Transform) {
+        self.origin = transform.position;
+        self.rotation = transform.rotation;
+        self.scale = transform.get_scale_matrix();
+    }
+
+    fn to_json(&self) -> Option<serde_json::Value> {
+        let description = EntityDescription {
+            id: self.id.clone(),
+            transform: self.get_transform()?,
+            properties: json!({ "model": self.model.name }),
+        };
+        Some(description.to_json("Square"))
+    }
+}
+
+#[derive(Clone)]
 pub struct Rectangle<'a> {
+    pub id: Option<String>,
     pub model: &'a Model,
     pub origin: Point3<f64>,
     pub rotation: Rotation3<f64>,
@@ -94,8 +187,61 @@ pub struct Rectangle<'a> {
     pub color: Color,
 }
 
-#[derive(Copy, Clone)]
+impl Entity for Rectangle<'_> {
+    fn get_id(&self) -> Option<&str> {
+        self.id.as_deref()
+    }
+
+    fn get_origin(&self) -> Point3<f64> {
+        self.origin
+    }
+
+    fn get_rotation(&self) -> Rotation3<f64> {
+        self.rotation
+    }
+
+    fn get_scale(&self) -> Matrix4<f64> {
+        self.scale
+    }
+
+    fn get_model(&self) -> &Model {
+        self.model
+    }
+
+    fn get_transform(&self) -> Option<Transform> {
+        Some(Transform::from_scale_matrix(
+            self.origin,
+            self.rotation,
+            &self.scale,
+        ))
+    }
+
+    fn set_transform(&mut self, transform: Transform) {
+        self.origin = transform.position;
+        self.rotation = transform.rotation;
+        self.scale = transform.get_scale_matrix();
+    }
+
+    fn get_maybe_color(&self) -> Option<Color> {
+        Some(self.color)
+    }
+
+    fn to_json(&self) -> Option<serde_json::Value> {
+        let description = EntityDescription {
+            id: self.id.clone(),
+            transform: self.get_transform()?,
+            properties: json!({
+                "model": self.model.name,
+                "color": [self.color.r, self.color.g, self.color.b],
+            }),
+        };
+        Some(description.to_json("Rectangle"))
+    }
+}
+
+#[derive(Clone)]
 pub struct SpinningObject<'a> {
+    pub id: Option<String>,
     pub model: &'a Model,
     pub origin: Point3<f64>,
     pub rotation: Rotation3<f64>,
@@ -105,8 +251,139 @@ pub struct SpinningObject<'a> {
     pub scale: Matrix4<f64>,
 }
 
-impl Updatable for SpinningObject<'_> {
+impl Entity for SpinningObject<'_> {
+    fn update(&mut self, delta_time: f64) {
+        self.rotation_angle += self.rotation_velocity * delta_time;
+    }
+
+    fn get_id(&self) -> Option<&str> {
+        self.id.as_deref()
+    }
+
+    fn get_origin(&self) -> Point3<f64> {
+        self.origin
+    }
+
+    fn get_rotation(&self) -> Rotation3<f64> {
+        let axis_rotation = Rotation3::from_axis_angle(
+            &Unit::new_normalize(self.rotation_axis),
+            self.rotation_angle,
+        );
+
+        axis_rotation * self.rotation
+    }
+
+    fn get_scale(&self) -> Matrix4<f64> {
+        self.scale
+    }
+
+    fn get_model(&self) -> &Model {
+        self.model
+    }
+
+    fn get_transform(&self) -> Option<Transform> {
+        Some(Transform::from_scale_matrix(
+            self.origin,
+            self.rotation,
+            &self.scale,
+        ))
+    }
+
+    fn set_transform(&mut self, transform: Transform) {
+        self.origin = transform.position;
+        self.rotation = transform.rotation;
+        self.scale = transform.get_scale_matrix();
+    }
+
+    // The spin angle isn't saved, so the saved object starts spinning from its rotation
+    fn to_json(&self) -> Option<serde_json::Value> {
+        let description = EntityDescription {
+            id: self.id.clone(),
+            transform: self.get_transform()?,
+            properties: json!({
+                "model": self.model.name,
+                "spin_axis": self.rotation_axis.as_slice(),
+                "angular_velocity": self.rotation_velocity,
+            }),
+        };
+        Some(description.to_json("SpinningObject"))
+    }
+}
+
+// Shared by entities that have no geometry of their own
+static EMPTY_MODEL: Model = Model {
+    name: String::new(),
+    geometry: Vec::new(),
+    materials: Vec::new(),
+    sub_meshes: Vec::new(),
+    fallback_faces: Vec::new(),
+};
+
+// Invisible scene graph node that positions and spins its children, e.g. an orbit
+#[derive(Clone)]
+pub struct Group {
+    pub id: Option<String>,
+    pub origin: Point3<f64>,
+    pub rotation: Rotation3<f64>,
+    pub rotation_axis: Vector3<f64>,
+    pub rotation_angle: f64,
+    pub rotation_velocity: f64,
+    pub scale: Matrix4<f64>,
+}
+
+impl Entity for Group {
     fn update(&mut self, delta_time: f64) {
         self.rotation_angle += self.rotation_velocity * delta_time;
     }
+
+    fn get_id(&self) -> Option<&str> {
+        self.id.as_deref()
+    }
+
+    fn get_origin(&self) -> Point3<f64> {
+        self.origin
+    }
+
+    fn get_rotation(&self) -> Rotation3<f64> {
+        let axis_rotation = Rotation3::from_axis_angle(
+            &Unit::new_normalize(self.rotation_axis),
+            self.rotation_angle,
+        );
+
+        axis_rotation * self.rotation
+    }
+
+    fn get_scale(&self) -> Matrix4<f64> {
+        self.scale
+    }
+
+    fn get_model(&self) -> &Model {
+        &EMPTY_MODEL
+    }
+
+    fn get_transform(&self) -> Option<Transform> {
+        Some(Transform::from_scale_matrix(
+            self.origin,
+            self.rotation,
+            &self.scale,
+        ))
+    }
+
+    fn set_transform(&mut self, transform: Transform) {
+        self.origin = transform.position;
+        self.rotation = transform.rotation;
+        self.scale = transform.get_scale_matrix();
+    }
+
+    fn to_json(&self) -> Option<serde_json::Value> {
+        let description = EntityDescription {
+            id: self.id.clone(),
+            transform: self.get_transform()?,
+            properties: json!({
+                "spin_axis": self.rotation_axis.as_slice(),
+                "angular_velocity": self.rotation_velocity,
+            }),
+        };
+        Some(description.to_json("Group"))
+    }
 }
@@ -0,0 +1,221 @@
+use std::collections::HashMap;
+
+use nalgebra::{UnitQuaternion, Vector3};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::entity::{validate_axis, Entity, Group, Rectangle, SpinningObject, Square, Transform};
+use crate::geometry::{Color, Model};
+use crate::models::ModelStore;
+
+// Builds an entity from its description in the scene file
+pub type EntityFactory =
+    Box<dyn for<'a> Fn(&EntityDescription, &'a ModelStore) -> Result<Box<dyn Entity + 'a>, String>>;
+
+/*
+    An object from the scene file, with the fields shared by every type already parsed by the
+    scene loader. The properties hold the remaining fields, which are specific to the type.
+*/
+#[derive(Clone, Debug, PartialEq)]
+pub struct EntityDescription {
+    pub id: Option<String>,
+    pub transform: Transform,
+    pub properties: serde_json::Value,
+}
+
+impl EntityDescription {
+    /*
+        The scene file object for the entity, the inverse of the scene loader's parsing. Rotations
+        are written as quaternions, and transform fields left at their defaults are left out.
+    */
+    pub fn to_json(&self, type_name: &str) -> serde_json::Value {
+        let mut object = serde_json::Map::new();
+        object.insert("type".to_string(), json!(type_name));
+        if let Some(id) = &self.id {
+            object.insert("id".to_string(), json!(id));
+        }
+
+        let transform = &self.transform;
+        let default_transform = Transform::new();
+        if transform.position != default_transform.position {
+            object.insert(
+                "position".to_string(),
+                json!(transform.position.coords.as_slice()),
+            );
+        }
+        if transform.rotation != default_transform.rotation {
+            let coords = UnitQuaternion::from_rotation_matrix(&transform.rotation).coords;
+            object.insert(
+                "rotation".to_string(),
+                json!({ "quaternion": [coords.x, coords.y, coords.z, coords.w] }),
+            );
+        }
+        let scale = transform.scale;
+        if scale != default_transform.scale {
+            let scale = if scale.x == scale.y && scale.y == scale.z {
+                json!(scale.x)
+            } else {
+                json!(scale.as_slice())
+            };
+            object.insert("scale".to_string(), scale);
+        }
+
+        if let Some(properties) = self.properties.as_object() {
+            object.extend(properties.clone());
+        }
+        serde_json::Value::Object(object)
+    }
+}
+
+/*
+    Maps the "type" names used in scene files to the factories that create the entities.
+    The default registry knows the built-in Square, Rectangle, SpinningObject and Group types, and
+    custom entities are added with register before loading the scene.
+*/
+pub struct EntityRegistry {
+    factories: HashMap<String, EntityFactory>,
+}
+
+impl Default for EntityRegistry {
+    fn default() -> Self {
+        let mut registry = Self::new();
+        registry.register("Square", |description, model_store| {
+            let square: JsonSquare = parse_entity_json(&description.properties)?;
+            let transform = description.transform;
+            Ok(Box::new(Square {
+                id: description.id.clone(),
+                model: find_model(model_store, &square.model)?,
+                origin: transform.position,
+                rotation: transform.rotation,
+                scale: transform.get_scale_matrix(),
+            }))
+        });
+        registry.register("Rectangle", |description, model_store| {
+            let rectangle: JsonRectangle = parse_entity_json(&description.properties)?;
+            let transform = description.transform;
+            Ok(Box::new(Rectangle {
+                id: description.id.clone(),
+                model: find_model(model_store, &rectangle.model)?,
+                origin: transform.position,
+                rotation: transform.rotation,
+                scale: transform.get_scale_matrix(),
+                color: Color::new(rectangle.color[0], rectangle.color[1], rectangle.color[2]),
+            }))
+        });
+        registry.register("SpinningObject", |description, model_store| {
+            let object: JsonSpinningObject = parse_entity_json(&description.properties)?;
+            validate_axis("spin_axis", &object.spin_axis)?;
+            let transform = description.transform;
+            Ok(Box::new(SpinningObject {
+                id: description.id.clone(),
+                model: find_model(model_store, &object.model)?,
+                origin: transform.position,
+                rotation: transform.rotation,
+                rotation_axis: Vector3::from(object.spin_axis),
+                rotation_angle: 0.0,
+                rotation_velocity: object.angular_velocity,
+                scale: transform.get_scale_matrix(),
+            }))
+        });
+        registry.register("Group", |description, _| {
+            let group: JsonGroup = parse_entity_json(&description.properties)?;
+            validate_axis("spin_axis", &group.spin_axis)?;
+            let transform = description.transform;
+            Ok(Box::new(Group {
+                id: description.id.clone(),
+                origin: transform.position,
+                rotation: transform.rotation,
+                rotation_axis: Vector3::from(group.spin_axis),
+                rotation_angle: 0.0,
+                rotation_velocity: group.angular_velocity,
+                scale: transform.get_scale_matrix(),
+            }))
+        });
+        registry
+    }
+}
+
+impl EntityRegistry {
+    // An empty registry, without the built-in entity types
+    pub fn new() -> Self {
+        EntityRegistry {
+            factories: HashMap::new(),
+        }
+    }
+
+    // Registers the factory for the type name, replacing any existing factory
+    pub fn register<F>(&mut self, type_name: &str, factory: F)
+    where
+        F: for<'a> Fn(&EntityDescription, &'a ModelStore) -> Result<Box<dyn Entity + 'a>, String>
+            + 'static,
+    {
+        self.factories
+            .insert(type_name.to_string(), Box::new(factory));
+    }
+
+    pub fn is_registered(&self, type_name: &str) -> bool {
+        self.factories.contains_key(type_name)
+    }
+
+    // Creates the entity with the factory registered for the type name
+    pub fn create<'a>(
+        &self,
+        type_name: &str,
+        description: &EntityDescription,
+        model_store: &'a ModelStore,
+    ) -> Result<Box<dyn Entity + 'a>, String> {
+        let factory = self
+            .factories
+            .get(type_name)
+            .ok_or_else(|| format!("Unknown entity type {}", type_name))?;
+
+        factory(description, model_store)
+    }
+}
+
+// Deserializes an entity's properties, for use by custom entity factories
+pub fn parse_entity_json<T: DeserializeOwned>(json: &serde_json::Value) -> Result<T, String> {
+    T::deserialize(json).map_err(|error| error.to_string())
+}
+
+fn find_model<'a>(model_store: &'a ModelStore, model_name: &str) -> Result<&'a Model, String> {
+    model_store
+        .find_model(model_name)
+        .ok_or_else(|| format!("Unknown model {}", model_name))
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct JsonSquare {
+    model: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct JsonRectangle {
+    model: String,
+    color: [u8; 3],
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct JsonSpinningObject {
+    model: String,
+    #[serde(default = "default_spin_axis")]
+    spin_axis: [f64; 3],
+    angular_velocity: f64,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct JsonGroup {
+    #[serde(default = "default_spin_axis")]
+    spin_axis: [f64; 3],
+    #[serde(default)]
+    angular_velocity: f64,
+}
+
+fn default_spin_axis() -> [f64; 3] {
+    [0.0, 1.0, 0.0]
+}
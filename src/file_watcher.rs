@@ -0,0 +1,103 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+/*
+    Watches files for changes by polling their modification times, so no OS specific file
+    notification dependency is needed. Watching a directory watches every file directly
+    inside it, including files added after it started being watched.
+*/
+pub struct FileWatcher {
+    paths: Vec<PathBuf>,
+    modified_times: HashMap<PathBuf, SystemTime>,
+    poll_interval: Duration,
+    last_poll: Instant,
+}
+
+impl FileWatcher {
+    pub fn new(poll_interval: Duration) -> Self {
+        FileWatcher {
+            paths: Vec::new(),
+            modified_times: HashMap::new(),
+            poll_interval,
+            last_poll: Instant::now(),
+        }
+    }
+
+    pub fn watch(&mut self, path: &Path) {
+        self.paths.push(path.to_path_buf());
+        self.modified_times.extend(get_modified_times(path));
+    }
+
+    pub fn unwatch_all(&mut self) {
+        self.paths.clear();
+        self.modified_times.clear();
+    }
+
+    // Takes the files as they are now to be unchanged, so files written by the app itself
+    // aren't reported by the next poll
+    pub fn mark_unchanged(&mut self) {
+        self.modified_times = self
+            .paths
+            .iter()
+            .flat_map(|path| get_modified_times(path))
+            .collect();
+    }
+
+    // Returns the files modified, added or removed since the last poll. Polls at most once
+    // per poll interval, returning nothing in between
+    pub fn poll(&mut self) -> Vec<PathBuf> {
+        if self.last_poll.elapsed() < self.poll_interval {
+            return Vec::new();
+        }
+        self.last_poll = Instant::now();
+
+        let modified_times: HashMap<PathBuf, SystemTime> = self
+            .paths
+            .iter()
+            .flat_map(|path| get_modified_times(path))
+            .collect();
+
+        let mut changed_paths: Vec<PathBuf> = modified_times
+            .iter()
+            .filter(|(path, modified_time)| self.modified_times.get(*path) != Some(modified_time))
+            .map(|(path, _)| path.clone())
+            .chain(
+                self.modified_times
+                    .keys()
+                    .filter(|path| !modified_times.contains_key(*path))
+                    .cloned(),
+            )
+            .collect();
+        changed_paths.sort();
+
+        self.modified_times = modified_times;
+        changed_paths
+    }
+}
+
+// Modification times of the file, or of the files directly inside the directory
+fn get_modified_times(path: &Path) -> Vec<(PathBuf, SystemTime)> {
+    let get_modified_time = |path: PathBuf| {
+        let modified_time = fs::metadata(&path).and_then(|metadata| metadata.modified());
+        modified_time
+            .ok()
+            .map(|modified_time| (path, modified_time))
+    };
+
+    if path.is_dir() {
+        fs::read_dir(path)
+            .map(|entries| {
+                entries
+                    .flatten()
+                    .map(|entry| entry.path())
+                    .filter(|path| path.is_file())
+                    .filter_map(get_modified_time)
+                    .collect()
+            })
+            .unwrap_or_default()
+    } else {
+        get_modified_time(path.to_path_buf()).into_iter().collect()
+    }
+}
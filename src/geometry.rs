@@ -1,3 +1,5 @@
+use std::ops::Range;
+
 use nalgebra::{Matrix4, Point2, Point3, Point4, Vector3};
 
 use crate::entity::Entity;
@@ -6,7 +8,55 @@ use crate::entity::Entity;
 
 #[derive(Debug)]
 pub struct Model {
+    // File name the model was loaded from, which scene files refer to it by
+    pub name: String,
     pub geometry: Vec<Triangle3>,
+    // Triangles using each named material of an OBJ model, in geometry order
+    pub materials: Vec<MaterialRange>,
+    // Triangles of each object and group of an OBJ model, in geometry order. A name appears
+    // more than once when the file returns to an earlier group, and the ranges of groups named
+    // on the same g line are the same
+    pub sub_meshes: Vec<SubMesh>,
+    // Triangles of each face without a material color, which are given a fallback color
+    pub fallback_faces: Vec<Range<usize>>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MaterialRange {
+    pub name: String,
+    pub triangles: Range<usize>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SubMesh {
+    pub name: String,
+    pub triangles: Range<usize>,
+}
+
+impl Model {
+    // Name of the material used by the triangle, None where the model has no material. The
+    // material ranges are in geometry order, so the range holding it is found by binary search
+    pub fn get_material_name(&self, triangle_index: usize) -> Option<&str> {
+        let range_index = self
+            .materials
+            .partition_point(|material| material.triangles.end <= triangle_index);
+        self.materials
+            .get(range_index)
+            .filter(|material| material.triangles.contains(&triangle_index))
+            .map(|material| material.name.as_str())
+    }
+
+    // Name of the object or group each triangle belongs to, None for models without groups.
+    // Triangles in several groups get the first one named
+    pub fn get_triangle_group_names(&self) -> Vec<Option<&str>> {
+        let mut names = vec![None; self.geometry.len()];
+        for sub_mesh in &self.sub_meshes {
+            for name in &mut names[sub_mesh.triangles.clone()] {
+                name.get_or_insert(sub_mesh.name.as_str());
+            }
+        }
+        names
+    }
 }
 
 #[derive(Copy, Clone, Debug, PartialEq)]
@@ -17,6 +67,8 @@ pub struct Color {
 }
 
 impl Color {
+    pub const BLACK: Color = Color { r: 0, g: 0, b: 0 };
+
     pub fn new(r: u8, g: u8, b: u8) -> Self {
         Self { r, g, b }
     }
@@ -26,6 +78,8 @@ impl Color {
 pub struct Triangle3 {
     pub vertices: [Point3<f64>; 3],
     pub color: Color,
+    // Light the triangle gives off, added to its lit color. Black for triangles that give off none
+    pub emission: Color,
     pub normal: Vector3<f64>,
 }
 
@@ -39,6 +93,8 @@ impl Triangle3 {
 pub struct Triangle4 {
     pub vertices: [Point4<f64>; 3],
     pub color: Color,
+    // Light the triangle gives off, added to its lit color. Black for triangles that give off none
+    pub emission: Color,
     pub normal: Vector3<f64>,
 }
 
@@ -63,8 +119,8 @@ impl BoundingBox2 {
         screen_height: usize,
     ) -> (usize, usize, usize, usize) {
         (
-            (self.x_min.floor() as usize).max(0),
-            (self.y_min.floor() as usize).max(0),
+            self.x_min.floor() as usize,
+            self.y_min.floor() as usize,
             (self.x_max.ceil() as usize).min(screen_width),
             (self.y_max.ceil() as usize).min(screen_height),
         )
@@ -80,16 +136,21 @@ pub struct ProjectionResult {
     pub screen_bounding_box: BoundingBox2,
 }
 
-pub fn transform_entity_model(entity: &Entity) -> Vec<Triangle3> {
-    let scale = entity.get_scale();
-    let rotation = Matrix4::from(entity.get_rotation());
-    let rotation_matrix = rotation.fixed_view::<3, 3>(0, 0);
-    let translation = Matrix4::new_translation(&entity.get_origin().coords);
+// Transforms the entity's model into world space. Normals are transformed by the inverse
+// transpose so they stay perpendicular to the surface under non uniform scaling. An entity with
+// a color of its own replaces the look of its whole model, so its model's emission is dropped
+pub fn transform_entity_model(entity: &dyn Entity, transform: &Matrix4<f64>) -> Vec<Triangle3> {
+    let linear = transform.fixed_view::<3, 3>(0, 0).into_owned();
+    let normal_matrix = linear
+        .try_inverse()
+        .map(|inverse| inverse.transpose())
+        .unwrap_or(linear);
 
-    let transform = translation * rotation * scale;
+    let entity_color = entity.get_maybe_color();
 
     let transformed_triangles_vec: Vec<Triangle3> = entity
-        .get_model_geometry()
+        .get_model()
+        .geometry
         .iter()
         .map(|triangle| {
             let transformed_vertices = triangle
@@ -98,7 +159,7 @@ pub fn transform_entity_model(entity: &Entity) -> Vec<Triangle3> {
                 .map(|vertex| transform.transform_point(vertex))
                 .collect::<Vec<Point3<f64>>>();
 
-            let transformed_normal = (rotation_matrix * triangle.normal).normalize();
+            let transformed_normal = (normal_matrix * triangle.normal).normalize();
 
             Triangle3 {
                 vertices: [
@@ -106,7 +167,11 @@ pub fn transform_entity_model(entity: &Entity) -> Vec<Triangle3> {
                     transformed_vertices[1],
                     transformed_vertices[2],
                 ],
-                color: entity.get_maybe_color().unwrap_or(triangle.color),
+                color: entity_color.unwrap_or(triangle.color),
+                emission: match entity_color {
+                    Some(_) => Color::BLACK,
+                    None => triangle.emission,
+                },
                 normal: transformed_normal,
             }
         })
@@ -220,11 +285,13 @@ fn clip_triangle_against_plane(plane: FrustumPlane, triangles: &[Triangle4]) ->
                         Triangle4 {
                             vertices: [*a.0, *b.0, i2],
                             color: rcolor_1,
+                            emission: triangle.emission,
                             normal: triangle.normal,
                         },
                         Triangle4 {
                             vertices: [*a.0, i2, i1],
                             color: rcolor_2,
+                            emission: triangle.emission,
                             normal: triangle.normal,
                         },
                     ]
@@ -237,11 +304,13 @@ fn clip_triangle_against_plane(plane: FrustumPlane, triangles: &[Triangle4]) ->
                         Triangle4 {
                             vertices: [*a.0, i1, *c.0],
                             color: rcolor_1,
+                            emission: triangle.emission,
                             normal: triangle.normal,
                         },
                         Triangle4 {
                             vertices: [*c.0, i1, i2],
                             color: rcolor_2,
+                            emission: triangle.emission,
                             normal: triangle.normal,
                         },
                     ]
@@ -253,6 +322,7 @@ fn clip_triangle_against_plane(plane: FrustumPlane, triangles: &[Triangle4]) ->
                     vec![Triangle4 {
                         vertices: [*a.0, i1, i2],
                         color: rcolor_2,
+                        emission: triangle.emission,
                         normal: triangle.normal,
                     }]
                 }
@@ -293,6 +363,111 @@ pub fn is_point_in_triangle(pt: &Point2<f64>, triangle: &Triangle3) -> bool {
     !(has_neg && has_pos)
 }
 
+// Distance in screen pixels from the point to the nearest edge of the triangle
+pub fn distance_to_triangle_edges(pt: &Point2<f64>, triangle: &Triangle3) -> f64 {
+    let distance_to_segment = |a: &Point3<f64>, b: &Point3<f64>| -> f64 {
+        let a = Point2::new(a.x, a.y);
+        let b = Point2::new(b.x, b.y);
+        let ab = b - a;
+        let length_squared = ab.norm_squared();
+        if length_squared == 0.0 {
+            return (pt - a).norm();
+        }
+        let t = ((pt - a).dot(&ab) / length_squared).clamp(0.0, 1.0);
+        (pt - (a + ab * t)).norm()
+    };
+
+    let (v0, v1, v2) = triangle.vertices();
+    distance_to_segment(v0, v1)
+        .min(distance_to_segment(v1, v2))
+        .min(distance_to_segment(v2, v0))
+}
+
+// Closest point on the triangle to p, by finding which vertex, edge or face region p lies in
+pub fn closest_point_on_triangle(p: &Point3<f64>, triangle: &Triangle3) -> Point3<f64> {
+    let (a, b, c) = triangle.vertices();
+    let ab = b - a;
+    let ac = c - a;
+
+    let ap = p - a;
+    let d1 = ab.dot(&ap);
+    let d2 = ac.dot(&ap);
+    if d1 <= 0.0 && d2 <= 0.0 {
+        return *a;
+    }
+
+    let bp = p - b;
+    let d3 = ab.dot(&bp);
+    let d4 = ac.dot(&bp);
+    if d3 >= 0.0 && d4 <= d3 {
+        return *b;
+    }
+
+    let vc = d1 * d4 - d3 * d2;
+    if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0 {
+        return a + ab * (d1 / (d1 - d3));
+    }
+
+    let cp = p - c;
+    let d5 = ab.dot(&cp);
+    let d6 = ac.dot(&cp);
+    if d6 >= 0.0 && d5 <= d6 {
+        return *c;
+    }
+
+    let vb = d5 * d2 - d1 * d6;
+    if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0 {
+        return a + ac * (d2 / (d2 - d6));
+    }
+
+    let va = d3 * d6 - d5 * d4;
+    if va <= 0.0 && (d4 - d3) >= 0.0 && (d5 - d6) >= 0.0 {
+        return b + (c - b) * ((d4 - d3) / ((d4 - d3) + (d5 - d6)));
+    }
+
+    let denominator = 1.0 / (va + vb + vc);
+    a + ab * (vb * denominator) + ac * (vc * denominator)
+}
+
+// Distance along the ray to where it hits either side of the triangle, using the
+// Moller-Trumbore algorithm
+pub fn ray_triangle_intersection(
+    origin: &Point3<f64>,
+    direction: &Vector3<f64>,
+    triangle: &Triangle3,
+) -> Option<f64> {
+    let (v0, v1, v2) = triangle.vertices();
+    let edge1 = v1 - v0;
+    let edge2 = v2 - v0;
+
+    let h = direction.cross(&edge2);
+    let a = edge1.dot(&h);
+    if a.abs() < 1e-12 {
+        // The ray is parallel to the triangle
+        return None;
+    }
+
+    let f = 1.0 / a;
+    let s = origin - v0;
+    let u = f * s.dot(&h);
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+
+    let q = s.cross(&edge1);
+    let v = f * direction.dot(&q);
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+
+    let t = f * edge2.dot(&q);
+    if t >= 0.0 {
+        Some(t)
+    } else {
+        None
+    }
+}
+
 pub fn transform_world_vertice_to_camera_coords(
     point: &Point3<f64>,
     camera_transform: &Matrix4<f64>,
@@ -315,6 +490,7 @@ fn transform_triangle_to_camera_coords(
     Triangle3 {
         vertices: [camera_v0, camera_v1, camera_v2],
         color: triangle.color,
+        emission: triangle.emission,
         normal: triangle.normal,
     }
 }
@@ -333,6 +509,7 @@ fn camera_coordinates_to_clip_space(
     Triangle4 {
         vertices: [v0, v1, v2],
         color: camera_triangle.color,
+        emission: camera_triangle.emission,
         normal: camera_triangle.normal,
     }
 }
@@ -350,6 +527,7 @@ fn transform_world_space_to_clip_space(
     Triangle4 {
         vertices: [v0, v1, v2],
         color: world_triangle.color,
+        emission: world_triangle.emission,
         normal: world_triangle.normal,
     }
 }
@@ -362,6 +540,7 @@ fn clips_space_to_ndc(clip_space_triangle: &Triangle4) -> Triangle3 {
     Triangle3 {
         vertices: [v0, v1, v2],
         color: clip_space_triangle.color,
+        emission: clip_space_triangle.emission,
         normal: clip_space_triangle.normal,
     }
 }
@@ -380,6 +559,7 @@ fn ndc_to_screen(ndc_triangle: &Triangle3, screen_width: usize, screen_height: u
     Triangle3 {
         vertices: [v0, v1, v2],
         color: ndc_triangle.color,
+        emission: ndc_triangle.emission,
         normal: ndc_triangle.normal,
     }
 }
@@ -436,12 +616,9 @@ pub fn interpolate_attributes_at_pixel(
 
     assert!(lambda0 + lambda1 + lambda2 < 1.00001 && lambda0 + lambda1 + lambda2 > 0.99999);
 
-    let iz0 = 1.0 / ndc_v0.z;
-    let iz1 = 1.0 / ndc_v1.z;
-    let iz2 = 1.0 / ndc_v2.z;
-
-    // Interpolate z depth
-    1.0 / (iz0 * lambda0 + iz1 * lambda1 + iz2 * lambda2)
+    // Interpolate z depth. NDC depth is affine in screen space for both perspective and
+    // orthographic projections, so it interpolates linearly with the screen barycentrics
+    ndc_v0.z * lambda0 + ndc_v1.z * lambda1 + ndc_v2.z * lambda2
 }
 
 pub fn project_triangle(
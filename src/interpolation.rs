@@ -0,0 +1,151 @@
+use std::ops::{Add, Mul, Sub};
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Copy, Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Easing {
+    #[default]
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+}
+
+impl Easing {
+    // Remaps t in the range [0, 1]
+    pub fn apply(&self, t: f64) -> f64 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t,
+            Easing::EaseOut => t * (2.0 - t),
+            Easing::EaseInOut => t * t * (3.0 - 2.0 * t),
+        }
+    }
+}
+
+pub fn lerp<T>(a: T, b: T, t: f64) -> T
+where
+    T: Copy + Add<Output = T> + Sub<Output = T> + Mul<f64, Output = T>,
+{
+    a + (b - a) * t
+}
+
+// Uniform Catmull-Rom spline through p1 and p2, with p0 and p3 shaping the tangents
+pub fn catmull_rom<T>(p0: T, p1: T, p2: T, p3: T, t: f64) -> T
+where
+    T: Copy + Add<Output = T> + Sub<Output = T> + Mul<f64, Output = T>,
+{
+    let t2 = t * t;
+    let t3 = t2 * t;
+
+    let m1 = (p2 - p0) * 0.5;
+    let m2 = (p3 - p1) * 0.5;
+
+    // Cubic Hermite basis
+    p1 * (2.0 * t3 - 3.0 * t2 + 1.0)
+        + m1 * (t3 - 2.0 * t2 + t)
+        + p2 * (-2.0 * t3 + 3.0 * t2)
+        + m2 * (t3 - t2)
+}
+
+/*
+    Finds the pair of keyframes surrounding the time, returning the index of the first and
+    the fraction of the way to the next. Times must be sorted in ascending order.
+*/
+pub fn find_segment(times: &[f64], time: f64) -> (usize, f64) {
+    if times.len() < 2 || time <= times[0] {
+        return (0, 0.0);
+    }
+
+    let last = times.len() - 1;
+    if time >= times[last] {
+        return (last - 1, 1.0);
+    }
+
+    let index = times.partition_point(|keyframe_time| *keyframe_time <= time) - 1;
+    let duration = times[index + 1] - times[index];
+    if duration <= 0.0 {
+        return (index, 1.0);
+    }
+    (index, (time - times[index]) / duration)
+}
+
+#[derive(Copy, Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PlaybackMode {
+    Once,
+    #[default]
+    Loop,
+    PingPong,
+}
+
+impl PlaybackMode {
+    // Maps an ever increasing time onto the [0, duration] range of the animation
+    pub fn wrap_time(&self, time: f64, duration: f64) -> f64 {
+        if duration <= 0.0 {
+            return 0.0;
+        }
+        match self {
+            PlaybackMode::Once => time.clamp(0.0, duration),
+            PlaybackMode::Loop => time.rem_euclid(duration),
+            PlaybackMode::PingPong => {
+                let t = time.rem_euclid(2.0 * duration);
+                if t > duration {
+                    2.0 * duration - t
+                } else {
+                    t
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_segment_returns_the_fraction_between_keyframes() {
+        let times = [0.0, 1.0, 3.0];
+        assert_eq!(find_segment(&times, -1.0), (0, 0.0));
+        assert_eq!(find_segment(&times, 0.5), (0, 0.5));
+        assert_eq!(find_segment(&times, 1.0), (1, 0.0));
+        assert_eq!(find_segment(&times, 2.5), (1, 0.75));
+        assert_eq!(find_segment(&times, 4.0), (1, 1.0));
+        // A single keyframe has no segment to move along
+        assert_eq!(find_segment(&[2.0], 5.0), (0, 0.0));
+        // Keyframes at the same time jump straight to the later one
+        assert_eq!(find_segment(&[0.0, 1.0, 1.0, 2.0], 1.0), (2, 0.0));
+    }
+
+    #[test]
+    fn playback_modes_wrap_the_time() {
+        let wrap = |mode: PlaybackMode, time: f64| mode.wrap_time(time, 2.0);
+        assert_eq!(wrap(PlaybackMode::Once, -1.0), 0.0);
+        assert_eq!(wrap(PlaybackMode::Once, 5.0), 2.0);
+        assert_eq!(wrap(PlaybackMode::Loop, 5.0), 1.0);
+        assert_eq!(wrap(PlaybackMode::Loop, -0.5), 1.5);
+        assert_eq!(wrap(PlaybackMode::PingPong, 1.5), 1.5);
+        assert_eq!(wrap(PlaybackMode::PingPong, 2.5), 1.5);
+        assert_eq!(wrap(PlaybackMode::PingPong, 4.5), 0.5);
+        assert_eq!(PlaybackMode::Loop.wrap_time(5.0, 0.0), 0.0);
+    }
+
+    #[test]
+    fn easings_keep_the_end_points() {
+        for easing in [
+            Easing::Linear,
+            Easing::EaseIn,
+            Easing::EaseOut,
+            Easing::EaseInOut,
+        ] {
+            assert_eq!(easing.apply(-1.0), 0.0);
+            assert_eq!(easing.apply(0.0), 0.0);
+            assert_eq!(easing.apply(1.0), 1.0);
+            assert_eq!(easing.apply(2.0), 1.0);
+        }
+        assert_eq!(Easing::EaseInOut.apply(0.5), 0.5);
+    }
+}
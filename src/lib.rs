@@ -1,9 +1,17 @@
+pub mod animation;
+pub mod behavior;
 pub mod buffer;
 pub mod camera;
+pub mod editor;
 pub mod entity;
+pub mod file_watcher;
 pub mod geometry;
+pub mod interpolation;
 pub mod light;
+pub mod material;
 pub mod models;
 pub mod renderer;
+pub mod scene;
+pub mod scene_graph;
 pub mod scene_loader;
 pub mod terminal;
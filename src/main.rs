@@ -1,43 +1,93 @@
 use std::io;
+use std::path::Path;
 use std::time;
 
+use crossterm::event::{MouseButton, MouseEvent, MouseEventKind};
 use include_dir::include_dir;
 use nalgebra::Point3;
 
-use lib_terminal_renderer::camera::ControllablePerspectiveCameraBuilder;
+use lib_terminal_renderer::camera::{
+    Camera, ControllablePerspectiveCameraBuilder, OrbitControlPerspectiveCameraBuilder,
+    OrbitingPerspectiveCameraBuilder, OrthographicCameraBuilder, OrthographicPreset,
+    StaticPerspectiveCameraBuilder,
+};
+use lib_terminal_renderer::editor::{Editor, Selection};
+use lib_terminal_renderer::entity::EntityRegistry;
+use lib_terminal_renderer::file_watcher::FileWatcher;
 use lib_terminal_renderer::models::ModelStore;
-use lib_terminal_renderer::renderer;
+use lib_terminal_renderer::renderer::{self, PickBuffer};
+use lib_terminal_renderer::scene::Scene;
+use lib_terminal_renderer::scene_graph::SceneGraph;
 use lib_terminal_renderer::scene_loader;
+use lib_terminal_renderer::terminal::input_event::InputEvent;
+use lib_terminal_renderer::terminal::key_bindings::{Action, InputConfig};
 use lib_terminal_renderer::terminal::Terminal;
 
-const BACKGROUND_COLOR: [u8; 3] = [100, 100, 100];
 const TARGET_FPS: usize = 20;
 const ASPECT_RATIO: f64 = 2.0; // 1.6
 const USE_TRUE_COLOR: bool = true;
 const USE_DITHERING: bool = false;
+// Fill the whole terminal instead of letterboxing to ASPECT_RATIO
+const FILL_TERMINAL: bool = false;
 
-const SCENE_FILE: &str = include_str!("../scenes/car_demo.json");
+const INPUT_CONFIG_FILE: &str = "config/input.json";
+const SCENE_FILES: [&str; 7] = [
+    "car_demo.json",
+    "demo.json",
+    "cornell_box_demo.json",
+    "teapot_demo.json",
+    "bunny_demo.json",
+    "cube_demo.json",
+    "solar_system_demo.json",
+];
+const SCENE_DIR: include_dir::Dir = include_dir!("scenes/");
+const SCENE_PATH: &str = scene_loader::SCENE_PATH;
 const MODEL_DIR: include_dir::Dir = include_dir!("models/");
+const MODEL_PATH: &str = "models";
+// How often the scene file and models are checked for changes
+const HOT_RELOAD_INTERVAL: time::Duration = time::Duration::from_millis(500);
 
 fn main() -> io::Result<()> {
-    let mut camera = ControllablePerspectiveCameraBuilder::new()
-        .origin(Point3::new(0.0, 0.7, 3.0))
-        .yaw(-std::f64::consts::PI / 2.0)
-        .pitch(-0.4)
-        .aspect_ratio(ASPECT_RATIO)
-        .build();
-
     let mut model_store = ModelStore::new(&MODEL_DIR);
     model_store.init();
 
-    let (mut entities, lights) = scene_loader::load_scene(SCENE_FILE, &model_store);
+    let mut scene_index = 0;
+    let (mut scene, scene_error) = load_scene_file(scene_index, &model_store);
+    // Cameras are cycled through at runtime with the next camera action
+    let mut cameras = take_scene_cameras(&mut scene, ASPECT_RATIO);
+    let mut camera_index = 0;
+
+    let mut editor = Editor::new();
+    // What the last frame drew at each pixel, for selecting entities with the mouse. It is
+    // cleared whenever the scene graph changes, so clicks don't pick entities that moved
+    let mut pick_buffer = PickBuffer::new(0, 0);
+    // Shown in the HUD, after the editor status while editing
+    let mut hud_message = scene_error;
+
+    let mut is_paused = false;
+    let mut use_true_color = USE_TRUE_COLOR;
 
     let mut start_time = time::Instant::now();
     let delay_duration = time::Duration::from_secs_f64(1.0 / TARGET_FPS as f64);
 
-    let mut terminal = Terminal::new(BACKGROUND_COLOR, ASPECT_RATIO, USE_TRUE_COLOR);
+    // The input config is read at runtime so keys can be rebound without rebuilding
+    let input_config = std::fs::read_to_string(INPUT_CONFIG_FILE)
+        .map(|json_string| {
+            InputConfig::from_json(&json_string)
+                .unwrap_or_else(|error| panic!("{}: {}", INPUT_CONFIG_FILE, error))
+        })
+        .unwrap_or_default();
+
+    let mut terminal = Terminal::new(scene.background_color, ASPECT_RATIO, use_true_color);
+    terminal.set_input_config(input_config);
+    terminal.set_fill_terminal(FILL_TERMINAL);
     terminal.init()?;
 
+    let mut file_watcher = FileWatcher::new(HOT_RELOAD_INTERVAL);
+    // The whole scene directory is watched, since scenes can include other scene files
+    file_watcher.watch(Path::new(SCENE_PATH));
+    file_watcher.watch(Path::new(MODEL_PATH));
+
     loop {
         // Sleep until the next loop time if needed
         std::thread::sleep(delay_duration.saturating_sub(start_time.elapsed()));
@@ -50,16 +100,174 @@ fn main() -> io::Result<()> {
         if terminal.is_ctrl_c_pressed() {
             break;
         }
-        camera.update(delta_time, &terminal.get_key_presses());
-        for entity in &mut entities {
-            entity.update(delta_time);
+
+        // Rebuild the camera projections to match the new screen dimensions
+        if terminal.was_resized() {
+            let aspect_ratio = terminal.get_aspect_ratio();
+            for camera in &mut cameras {
+                camera.set_aspect_ratio(aspect_ratio);
+            }
+        }
+
+        let triggered_actions = terminal.get_triggered_actions();
+        if triggered_actions.contains(&Action::Quit) {
+            break;
+        }
+        for action in &triggered_actions {
+            match action {
+                Action::TogglePause => is_paused = !is_paused,
+                Action::NextScene => {
+                    scene_index = (scene_index + 1) % SCENE_FILES.len();
+                    (scene, hud_message) = load_scene_file(scene_index, &model_store);
+                    editor.select(&scene, None);
+                    pick_buffer = PickBuffer::new(0, 0);
+
+                    // Swap out the cameras defined by the previous scene
+                    cameras = take_scene_cameras(&mut scene, terminal.get_aspect_ratio());
+                    camera_index = 0;
+                }
+                Action::NextCamera => camera_index = (camera_index + 1) % cameras.len(),
+                Action::ToggleWireframe => {
+                    scene.render_options.wireframe = !scene.render_options.wireframe
+                }
+                Action::ToggleColorMode => {
+                    use_true_color = !use_true_color;
+                    terminal.set_use_true_color(use_true_color);
+                }
+                Action::SaveCamera | Action::SaveScene => {
+                    let saved = if *action == Action::SaveCamera {
+                        save_cameras(scene_index, &cameras)
+                    } else {
+                        save_scene(scene_index, &scene, &cameras)
+                    };
+                    hud_message = Some(saved.unwrap_or_else(|error| error));
+                    // The scene in memory already matches what was saved
+                    file_watcher.mark_unchanged();
+                }
+                _ => {}
+            }
+            editor.trigger_action(
+                *action,
+                &mut scene,
+                &model_store,
+                cameras[camera_index].as_ref(),
+            );
+            // Adding and removing entities changes the indices the last frame's picks refer to
+            if matches!(
+                action,
+                Action::EditorAddObject | Action::EditorRemoveSelection
+            ) {
+                pick_buffer = PickBuffer::new(0, 0);
+            }
+            cameras[camera_index].trigger_action(*action);
+        }
+
+        // Reload the scene when its file or the models change on disk. The cameras are kept,
+        // so the view stays where it was while editing
+        let changed_paths = file_watcher.poll();
+        if !changed_paths.is_empty() {
+            let model_path = Path::new(MODEL_PATH);
+            let changed_models: Vec<String> = changed_paths
+                .iter()
+                .filter(|path| path.parent() == Some(model_path))
+                .filter_map(|path| Some(path.file_name()?.to_str()?.to_string()))
+                .collect();
+
+            if changed_models.is_empty() {
+                // A scene with errors leaves the previous scene in place
+                match try_load_scene_file(scene_index, &model_store) {
+                    Ok(reloaded_scene) => {
+                        scene = reloaded_scene;
+                        hud_message = None;
+                    }
+                    Err(error) => hud_message = Some(error),
+                }
+            } else {
+                // The scene borrows its models, so it is dropped while they are rebuilt. Like a
+                // scene with errors, models with errors leave the previous scene in place, which
+                // is rebuilt from its description
+                let previous_scene = scene_loader::scene_to_json(&scene).ok();
+                drop(scene);
+                let model_error = model_store.reload_files(model_path, &changed_models).err();
+                let reloaded_scene = match model_error {
+                    Some(model_error) => Err(model_error),
+                    None => try_load_scene_file(scene_index, &model_store),
+                };
+                (scene, hud_message) = match reloaded_scene {
+                    Ok(reloaded_scene) => (reloaded_scene, None),
+                    Err(error) => {
+                        let previous_scene = previous_scene.and_then(|json| {
+                            scene_loader::try_load_scene(&json.to_string(), &model_store).ok()
+                        });
+                        (
+                            previous_scene
+                                .unwrap_or_else(|| Scene::new(SceneGraph::new(), Vec::new())),
+                            Some(error),
+                        )
+                    }
+                };
+            }
+            editor.select(&scene, None);
+            pick_buffer = PickBuffer::new(0, 0);
+        }
+
+        let camera = &mut cameras[camera_index];
+        camera.update(
+            delta_time,
+            &terminal.get_actions(),
+            &terminal.get_mouse_state(),
+        );
+        editor.update(delta_time, &terminal.get_actions(), &mut scene);
+        if !is_paused {
+            scene.scene_graph.update(delta_time);
+        }
+        scene.scene_graph.set_view_position(camera.get_position());
+        camera.update_from_scene(&scene.scene_graph);
+
+        // Clicking selects the entity under the mouse
+        let clicked_pixel = terminal
+            .get_input_events()
+            .iter()
+            .filter_map(|event| match event {
+                InputEvent::Mouse(MouseEvent {
+                    kind: MouseEventKind::Down(MouseButton::Left),
+                    column,
+                    row,
+                    ..
+                }) => terminal.get_pixel_at(*column, *row),
+                _ => None,
+            })
+            .next_back();
+        if let Some((x, y)) = clicked_pixel.filter(|_| editor.is_active()) {
+            let picked = pick_buffer.pick(x, y);
+            editor.select(
+                &scene,
+                picked.map(|picked| Selection::Entity(picked.entity_index)),
+            );
+        }
+
+        if editor.is_active() {
+            let status = editor.get_status(&scene, &model_store);
+            terminal.set_hud_message(Some(match &hud_message {
+                Some(hud_message) => format!("{} | {}", status, hud_message),
+                None => status,
+            }));
+        } else {
+            terminal.set_hud_message(hud_message.clone());
         }
 
         // Renders the scene to the screen_buffer
         let screen_buffer = terminal.get_mutable_screen_buffer_reference();
-        renderer::render_scene(screen_buffer, &entities, &lights, &camera, BACKGROUND_COLOR);
+        pick_buffer = renderer::render_scene(
+            screen_buffer,
+            &scene.scene_graph,
+            &scene.lights,
+            camera.as_ref(),
+            scene.background_color,
+            &scene.render_options,
+        );
 
-        if USE_DITHERING && !USE_TRUE_COLOR {
+        if USE_DITHERING && !use_true_color {
             renderer::apply_ansi_256_dithering(screen_buffer);
         }
 
@@ -70,3 +278,130 @@ fn main() -> io::Result<()> {
 
     Ok(())
 }
+
+// Scene files are read from disk so edits to them can be reloaded, falling back to the copy
+// built into the binary when run outside of the repository. Included files are found the same way
+fn read_scene_file(file_name: &str) -> Result<String, String> {
+    std::fs::read_to_string(Path::new(SCENE_PATH).join(file_name)).or_else(|_| {
+        SCENE_DIR
+            .get_file(file_name)
+            .and_then(|file| file.contents_utf8())
+            .map(|contents| contents.to_string())
+            .ok_or_else(|| format!("Scene file {} not found", file_name))
+    })
+}
+
+fn try_load_scene_file<'a>(
+    scene_index: usize,
+    model_store: &'a ModelStore,
+) -> Result<Scene<'a>, String> {
+    let json_string = read_scene_file(SCENE_FILES[scene_index])?;
+    scene_loader::try_load_scene_with_includes(
+        &json_string,
+        model_store,
+        &EntityRegistry::default(),
+        &read_scene_file,
+    )
+    .map_err(|error| format!("Failed to load scene: {}", error))
+}
+
+// Writes the cameras with their current views into the scene file, keeping the rest of the file
+fn save_cameras(scene_index: usize, cameras: &[Box<dyn Camera>]) -> Result<String, String> {
+    let file_name = SCENE_FILES[scene_index];
+    let (json_string, migrated_from) =
+        scene_loader::replace_cameras(&read_scene_file(file_name)?, cameras)
+            .map_err(|error| format!("Failed to save cameras: {}", error))?;
+    let path = Path::new(SCENE_PATH).join(file_name);
+    std::fs::write(&path, json_string)
+        .map_err(|error| format!("Failed to write {}: {}", path.display(), error))?;
+    Ok(match migrated_from {
+        Some(version) => format!(
+            "Saved cameras to {}, migrating it from scene version {} to {}",
+            path.display(),
+            version,
+            scene_loader::SCENE_VERSION
+        ),
+        None => format!("Saved cameras to {}", path.display()),
+    })
+}
+
+// Writes the whole scene next to its scene file, which is left as it is since the saved scene
+// has its includes and prefabs expanded
+fn save_scene(
+    scene_index: usize,
+    scene: &Scene,
+    cameras: &[Box<dyn Camera>],
+) -> Result<String, String> {
+    let mut json = scene_loader::scene_to_json(scene)
+        .map_err(|error| format!("Failed to save scene: {}", error))?;
+    let cameras = scene_loader::cameras_to_json(cameras)
+        .map_err(|error| format!("Failed to save scene: {}", error))?;
+    json["cameras"] = serde_json::Value::Array(cameras);
+
+    let file_stem = SCENE_FILES[scene_index].trim_end_matches(".json");
+    let path = Path::new(SCENE_PATH).join(format!("{}_saved.json", file_stem));
+    std::fs::write(&path, scene_loader::to_json_string(&json))
+        .map_err(|error| format!("Failed to write {}: {}", path.display(), error))?;
+    Ok(format!("Saved scene to {}", path.display()))
+}
+
+// Loads the scene, or an empty scene along with the error to show if it has errors
+fn load_scene_file<'a>(
+    scene_index: usize,
+    model_store: &'a ModelStore,
+) -> (Scene<'a>, Option<String>) {
+    match try_load_scene_file(scene_index, model_store) {
+        Ok(scene) => (scene, None),
+        Err(error) => (Scene::new(SceneGraph::new(), Vec::new()), Some(error)),
+    }
+}
+
+// Takes the cameras declared by the scene, falling back to the built-in cameras if it has none
+fn take_scene_cameras(scene: &mut Scene, aspect_ratio: f64) -> Vec<Box<dyn Camera>> {
+    let mut cameras = std::mem::take(&mut scene.cameras);
+    if cameras.is_empty() {
+        cameras = default_cameras();
+    }
+    for camera in &mut cameras {
+        camera.set_aspect_ratio(aspect_ratio);
+    }
+    cameras
+}
+
+fn default_cameras() -> Vec<Box<dyn Camera>> {
+    vec![
+        Box::new(
+            ControllablePerspectiveCameraBuilder::new()
+                .origin(Point3::new(0.0, 0.7, 3.0))
+                .yaw(-std::f64::consts::PI / 2.0)
+                .pitch(-0.4)
+                .build(),
+        ),
+        Box::new(
+            OrbitControlPerspectiveCameraBuilder::new()
+                .target(Point3::new(0.0, 0.0, 0.0))
+                .distance(3.0)
+                .build(),
+        ),
+        Box::new(
+            OrbitingPerspectiveCameraBuilder::new()
+                .origin(Point3::new(0.0, 0.7, 3.0))
+                .fov(1.0)
+                .build(),
+        ),
+        Box::new(
+            StaticPerspectiveCameraBuilder::new()
+                .origin(Point3::new(0.0, 0.7, 3.0))
+                .fov(1.0)
+                .build(),
+        ),
+        Box::new(
+            OrthographicCameraBuilder::new()
+                .origin(Point3::new(0.0, 0.0, 10.0))
+                .target(Point3::new(0.0, 0.0, 0.0))
+                .view_height(3.0)
+                .preset(OrthographicPreset::Isometric)
+                .build(),
+        ),
+    ]
+}
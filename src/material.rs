@@ -0,0 +1,203 @@
+use std::collections::HashMap;
+
+#[cfg(feature = "random_colors")]
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use crate::geometry::{Color, Model, Triangle3};
+
+// Color of faces without a material color under the default fallback color
+pub const FALLBACK_GRAY: Color = Color {
+    r: 180,
+    g: 180,
+    b: 180,
+};
+
+/*
+    How faces without a material color are colored, such as OBJ faces before any usemtl or
+    using a material with no Kd. Every choice gives the same colors on every launch.
+*/
+#[derive(Copy, Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FallbackColor {
+    // The same gray for every face
+    #[default]
+    Gray,
+    // A color hashed from the name of the face's object or group, so the parts of a model
+    // stand out from each other
+    Group,
+    // A random color for each face, generated from the seed
+    #[cfg(feature = "random_colors")]
+    Random(u64),
+}
+
+impl FallbackColor {
+    // The color of each triangle in a face without a material color, None for the others.
+    // Triangles of the same face share a color
+    pub fn get_triangle_colors(&self, model: &Model) -> Vec<Option<Color>> {
+        let mut colors = vec![None; model.geometry.len()];
+        let group_names = model.get_triangle_group_names();
+        #[cfg(feature = "random_colors")]
+        let mut rng = StdRng::seed_from_u64(match self {
+            FallbackColor::Random(seed) => *seed,
+            _ => 0,
+        });
+
+        for face in &model.fallback_faces {
+            let color = match self {
+                FallbackColor::Gray => FALLBACK_GRAY,
+                FallbackColor::Group => {
+                    let group_name = group_names.get(face.start).copied().flatten();
+                    hash_color(group_name.unwrap_or(""))
+                }
+                #[cfg(feature = "random_colors")]
+                FallbackColor::Random(_) => Color::new(rng.gen(), rng.gen(), rng.gen()),
+            };
+            for triangle_color in &mut colors[face.clone()] {
+                *triangle_color = Some(color);
+            }
+        }
+        colors
+    }
+}
+
+// A color that is always the same for the name, kept light enough to show shading. Uses the
+// FNV-1a hash, since the standard library's hasher may change between Rust versions
+fn hash_color(name: &str) -> Color {
+    let hash = name.bytes().fold(0xcbf29ce484222325_u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    });
+    let channel = |shift: u32| (64 + ((hash >> shift) & 0xff) * 191 / 255) as u8;
+    Color::new(channel(0), channel(8), channel(16))
+}
+
+/*
+    Changes to the look of one entity's model, so a shared model can appear in several
+    colors in a scene. Faces without a material color are recolored first, then named
+    material colors are replaced, then the tint multiplies every color, including an
+    entity's own color override. A replaced material color also drops the material's
+    emission, so the faces don't keep glowing in the old color.
+*/
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MaterialOverride {
+    // Multiplier for each color channel
+    pub tint: [f64; 3],
+    // Replacement colors for named MTL materials
+    pub colors: HashMap<String, [u8; 3]>,
+    // Named MTL materials that aren't drawn
+    pub hidden: Vec<String>,
+    pub visible: bool,
+    // Replaces the colors of faces without a material color
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fallback_color: Option<FallbackColor>,
+}
+
+impl Default for MaterialOverride {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MaterialOverride {
+    pub fn new() -> Self {
+        MaterialOverride {
+            tint: [1.0, 1.0, 1.0],
+            colors: HashMap::new(),
+            hidden: Vec::new(),
+            visible: true,
+            fallback_color: None,
+        }
+    }
+
+    #[allow(unused)]
+    pub fn tint(&mut self, tint: [f64; 3]) -> &mut Self {
+        self.tint = tint;
+        self
+    }
+
+    #[allow(unused)]
+    pub fn color(&mut self, material_name: &str, color: Color) -> &mut Self {
+        self.colors
+            .insert(material_name.to_string(), [color.r, color.g, color.b]);
+        self
+    }
+
+    #[allow(unused)]
+    pub fn hide(&mut self, material_name: &str) -> &mut Self {
+        self.hidden.push(material_name.to_string());
+        self
+    }
+
+    #[allow(unused)]
+    pub fn visible(&mut self, visible: bool) -> &mut Self {
+        self.visible = visible;
+        self
+    }
+
+    #[allow(unused)]
+    pub fn fallback_color(&mut self, fallback_color: FallbackColor) -> &mut Self {
+        self.fallback_color = Some(fallback_color);
+        self
+    }
+
+    // Names of the overridden materials the model doesn't have, which are likely typos
+    pub fn get_unknown_materials(&self, model: &Model) -> Vec<String> {
+        let mut unknown: Vec<String> = self
+            .colors
+            .keys()
+            .chain(self.hidden.iter())
+            .filter(|name| {
+                !model
+                    .materials
+                    .iter()
+                    .any(|material| &material.name == *name)
+            })
+            .cloned()
+            .collect();
+        unknown.sort();
+        unknown.dedup();
+        unknown
+    }
+
+    // Recolors and hides the model's triangles, each given with its index in the model's geometry
+    pub fn apply(
+        &self,
+        model: &Model,
+        triangles: Vec<(usize, Triangle3)>,
+    ) -> Vec<(usize, Triangle3)> {
+        if !self.visible {
+            return Vec::new();
+        }
+
+        triangles
+            .into_iter()
+            .map(|(index, triangle)| (index, triangle, model.get_material_name(index)))
+            .filter(|(_, _, name)| !name.is_some_and(|name| self.hidden.iter().any(|h| h == name)))
+            .map(|(index, triangle, name)| {
+                let replacement = name.and_then(|name| self.colors.get(name));
+                let color = replacement
+                    .map(|color| Color::new(color[0], color[1], color[2]))
+                    .unwrap_or(triangle.color);
+                let triangle = Triangle3 {
+                    color: self.apply_tint(color),
+                    emission: match replacement {
+                        Some(_) => Color::BLACK,
+                        None => triangle.emission,
+                    },
+                    ..triangle
+                };
+                (index, triangle)
+            })
+            .collect()
+    }
+
+    fn apply_tint(&self, color: Color) -> Color {
+        let tint = |channel: u8, tint: f64| (channel as f64 * tint).round().clamp(0.0, 255.0) as u8;
+        Color::new(
+            tint(color.r, self.tint[0]),
+            tint(color.g, self.tint[1]),
+            tint(color.b, self.tint[2]),
+        )
+    }
+}
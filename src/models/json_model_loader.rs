@@ -15,13 +15,13 @@ struct Triangle {
     color: [u8; 3],
 }
 
-pub fn load_model(json_string: &str) -> Model {
+pub fn load_model(json_string: &str) -> Result<Model, String> {
     let geometry_data: GeometryData = serde_json::from_str(json_string)
-        .unwrap_or_else(|_| panic!("Failed to deserialize json {}", json_string));
+        .map_err(|error| format!("Failed to deserialize json: {}", error))?;
 
     let model_geometry: Model = convert_geometry_data(&geometry_data);
 
-    model_geometry
+    Ok(model_geometry)
 }
 
 fn convert_geometry_data(geometry_data: &GeometryData) -> Model {
@@ -44,12 +44,19 @@ fn convert_geometry_data(geometry_data: &GeometryData) -> Model {
                     g: color[1],
                     b: color[2],
                 },
-                normal: normal,
+                emission: Color::BLACK,
+                normal,
             }
         })
         .collect();
 
-    Model { geometry }
+    Model {
+        name: String::new(),
+        geometry,
+        materials: Vec::new(),
+        sub_meshes: Vec::new(),
+        fallback_faces: Vec::new(),
+    }
 }
 
 fn calculate_triangle_normal(triangle: &Triangle) -> Vector3<f64> {
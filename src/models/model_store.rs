@@ -1,4 +1,5 @@
 use crate::geometry::Model;
+use crate::material::FallbackColor;
 use crate::models::json_model_loader;
 use crate::models::mtl_loader;
 use crate::models::mtl_loader::Material;
@@ -7,10 +8,14 @@ use crate::models::obj_model_loader;
 use include_dir::Dir;
 use include_dir::File;
 use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
 
 pub struct ModelStore<'a> {
     models: HashMap<String, Model>,
+    material_store: MaterialStore,
     dir: &'a Dir<'a>,
+    fallback_color: FallbackColor,
 }
 
 #[derive(Debug)]
@@ -24,55 +29,124 @@ struct FileInfo<'b> {
     pub file_contents: &'b str,
 }
 
+// Models reference the materials of MTL files, so those are loaded before OBJ files
+const FILE_TYPE_ORDER: [&str; 3] = ["json", "mtl", "obj"];
+
 impl<'a> ModelStore<'a> {
     pub fn new(dir: &'a Dir<'a>) -> Self {
         ModelStore {
             models: HashMap::new(),
+            material_store: MaterialStore::new(),
             dir,
+            fallback_color: FallbackColor::default(),
         }
     }
 
-    pub fn init(&mut self) {
-        let mut material_store: MaterialStore = MaterialStore::new();
+    // Colors the faces without a material color in the models loaded from here on. Scenes can
+    // recolor them with their own fallback color
+    pub fn set_fallback_color(&mut self, fallback_color: FallbackColor) {
+        self.fallback_color = fallback_color;
+    }
 
-        // Process json files first
-        self.dir
-            .files()
-            .flat_map(|file| get_file_info(file))
-            .filter(|info| info.file_type == "json")
-            .for_each(|info| {
-                let model_geometry = json_model_loader::load_model(info.file_contents);
-                self.models
-                    .insert(info.file_name.to_string(), model_geometry);
-            });
-
-        // Process mtl files second
-        self.dir
-            .files()
-            .flat_map(|file| get_file_info(file))
-            .filter(|info| info.file_type == "mtl")
-            .for_each(|info| {
-                material_store.put(
-                    &info.file_name,
-                    &mtl_loader::parse_materials(info.file_contents),
-                )
-            });
-
-        // Process obj files third
-        self.dir
+    pub fn init(&mut self) {
+        let files: Vec<FileInfo> = self
+            .dir
             .files()
             .flat_map(|file| get_file_info(file))
-            .filter(|info| info.file_type == "obj")
-            .for_each(|info| {
-                let model_geometry =
-                    obj_model_loader::load_model(info.file_contents, &material_store);
-                //println!("{:#?}", model_geometry);
-                self.models
-                    .insert(info.file_name.to_string(), model_geometry);
-            });
-
-        //println!("{:#?}", material_store);
-        //println!("{:#?}", self.models);
+            .collect();
+
+        if let Err(error) = self.load_files(&files) {
+            panic!("{}", error);
+        }
+    }
+
+    /*
+        Rebuilds the models of the changed files, reading them from the model directory on
+        disk instead of the embedded one. Changing a MTL file reloads every OBJ file, since
+        any of them may use its materials. Files that fail to load keep their previous model,
+        and files that no longer exist are skipped.
+    */
+    pub fn reload_files(&mut self, dir_path: &Path, file_names: &[String]) -> Result<(), String> {
+        let mut file_names: Vec<String> = file_names.to_vec();
+        if file_names
+            .iter()
+            .any(|file_name| get_file_type(file_name) == Some("mtl"))
+        {
+            let obj_file_names = fs::read_dir(dir_path)
+                .map_err(|error| format!("{}: {}", dir_path.display(), error))?
+                .flatten()
+                .filter_map(|entry| entry.file_name().into_string().ok())
+                .filter(|file_name| get_file_type(file_name) == Some("obj"));
+            file_names.extend(obj_file_names);
+        }
+        file_names.sort();
+        file_names.dedup();
+
+        let contents: Vec<(String, String)> = file_names
+            .into_iter()
+            .filter_map(|file_name| {
+                let file_contents = fs::read_to_string(dir_path.join(&file_name)).ok()?;
+                Some((file_name, file_contents))
+            })
+            .collect();
+        let files: Vec<FileInfo> = contents
+            .iter()
+            .filter_map(|(file_name, file_contents)| {
+                Some(FileInfo {
+                    file_name,
+                    file_type: get_file_type(file_name)?,
+                    file_contents,
+                })
+            })
+            .collect();
+
+        self.load_files(&files)
+    }
+
+    // Loads the files in dependency order, returning the first file that failed to load
+    fn load_files(&mut self, files: &[FileInfo]) -> Result<(), String> {
+        let mut first_error = None;
+        for file_type in FILE_TYPE_ORDER {
+            for info in files.iter().filter(|info| info.file_type == file_type) {
+                let model = match file_type {
+                    "json" => json_model_loader::load_model(info.file_contents),
+                    "mtl" => {
+                        // Files that fail to parse keep their previous materials
+                        match mtl_loader::parse_materials(info.file_contents) {
+                            Ok(materials) => self.material_store.put(info.file_name, &materials),
+                            Err(error) => {
+                                first_error.get_or_insert_with(|| {
+                                    format!(
+                                        "Failed to load materials {}: {}",
+                                        info.file_name, error
+                                    )
+                                });
+                            }
+                        }
+                        continue;
+                    }
+                    _ => obj_model_loader::load_model(
+                        info.file_contents,
+                        &self.material_store,
+                        self.fallback_color,
+                    ),
+                };
+
+                match model {
+                    Ok(mut model) => {
+                        model.name = info.file_name.to_string();
+                        self.models.insert(info.file_name.to_string(), model);
+                    }
+                    Err(error) => {
+                        first_error.get_or_insert_with(|| {
+                            format!("Failed to load model {}: {}", info.file_name, error)
+                        });
+                    }
+                }
+            }
+        }
+
+        first_error.map_or(Ok(()), Err)
     }
 
     pub fn get_model(&self, model_name: &str) -> &Model {
@@ -80,6 +154,21 @@ impl<'a> ModelStore<'a> {
             .get(model_name)
             .unwrap_or_else(|| panic!("Could not get model of name {}", model_name))
     }
+
+    pub fn find_model(&self, model_name: &str) -> Option<&Model> {
+        self.models.get(model_name)
+    }
+
+    // Names of the loaded models in alphabetical order
+    pub fn get_model_names(&self) -> Vec<&str> {
+        let mut model_names: Vec<&str> = self.models.keys().map(String::as_str).collect();
+        model_names.sort();
+        model_names
+    }
+}
+
+fn get_file_type(file_name: &str) -> Option<&str> {
+    Path::new(file_name).extension()?.to_str()
 }
 
 fn get_file_info<'b>(file: &'b File<'b>) -> Option<FileInfo<'b>> {
@@ -102,10 +191,7 @@ impl MaterialStore {
     }
 
     pub fn put(&mut self, file_name: &str, materials: &HashMap<String, Material>) {
-        let file_materials = self
-            .materials
-            .entry(file_name.to_string())
-            .or_insert_with(HashMap::new);
+        let file_materials = self.materials.entry(file_name.to_string()).or_default();
 
         for (material_name, material) in materials {
             file_materials.insert(material_name.clone(), material.clone());
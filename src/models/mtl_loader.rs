@@ -1,47 +1,140 @@
 use std::collections::HashMap;
 
+// Statements naming a texture file, with the file name as the last argument after any options
+const TEXTURE_MAP_STATEMENTS: [&str; 11] = [
+    "map_Ka", "map_Kd", "map_Ks", "map_Ke", "map_Ns", "map_d", "map_bump", "bump", "disp", "decal",
+    "refl",
+];
+
+/*
+    Material of a MTL file. Colors are rgb values, which are clamped between 0 and 1 when the
+    model is drawn. The diffuse color is left out when the file doesn't give one, so the faces
+    using the material get the fallback color.
+    Statements that aren't given keep the defaults of the MTL format.
+*/
 #[derive(Debug, Clone)]
 pub struct Material {
     pub name: String,
+    pub ka: (f32, f32, f32),
     pub kd: Option<(f32, f32, f32)>,
+    pub ks: (f32, f32, f32),
+    pub ke: (f32, f32, f32),
+    // Specular exponent
+    pub ns: f32,
+    // Opacity from d, or 1 - Tr
+    pub dissolve: f32,
+    pub illum: u32,
+    // File names by statement, such as map_Kd or bump
+    pub texture_maps: HashMap<String, String>,
 }
 
-pub fn parse_materials(file_contents: &str) -> HashMap<String, Material> {
+impl Material {
+    pub fn new(name: &str) -> Self {
+        Material {
+            name: name.to_string(),
+            ka: (0.0, 0.0, 0.0),
+            kd: None,
+            ks: (0.0, 0.0, 0.0),
+            ke: (0.0, 0.0, 0.0),
+            ns: 0.0,
+            dissolve: 1.0,
+            illum: 0,
+            texture_maps: HashMap::new(),
+        }
+    }
+}
+
+/*
+    Parses the materials of a MTL file. Colors given in the unsupported spectral or xyz forms
+    keep their default, like the statements the renderer has no use for, such as Ni or Tf, which
+    are ignored.
+*/
+pub fn parse_materials(file_contents: &str) -> Result<HashMap<String, Material>, String> {
     let mut materials: HashMap<String, Material> = HashMap::new();
 
     let mut current_material: Option<Material> = None;
 
-    for line in file_contents.lines() {
+    for (line_index, line) in file_contents.lines().enumerate() {
         let line = line.trim();
+        let line_error = |message: &str| format!("Line {}: {}", line_index + 1, message);
 
         if line.is_empty() || line.starts_with('#') {
             continue;
         }
 
         let mut parts = line.split_whitespace();
-        match parts.next() {
-            Some("newmtl") => {
-                // Save the previous material
-                if let Some(material) = current_material.take() {
-                    materials.insert(material.name.clone(), material);
-                }
+        let Some(statement) = parts.next() else {
+            continue;
+        };
+        if statement == "newmtl" {
+            // Save the previous material
+            if let Some(material) = current_material.take() {
+                materials.insert(material.name.clone(), material);
+            }
 
-                // Start a new material
-                if let Some(name) = parts.next() {
-                    current_material = Some(Material {
-                        name: name.to_string(),
-                        kd: None,
-                    });
-                }
+            // Start a new material
+            let names: Vec<&str> = parts.collect();
+            if names.is_empty() {
+                return Err(line_error("Unable to process material name"));
             }
-            Some("Kd") => {
-                if let Some(material) = current_material.as_mut() {
-                    let kd_values: Vec<f32> = parts.filter_map(|p| p.parse::<f32>().ok()).collect();
+            current_material = Some(Material::new(&names.join(" ")));
+            continue;
+        }
 
-                    material.kd = Some((kd_values[0], kd_values[1], kd_values[2]));
+        let is_known_statement = matches!(
+            statement,
+            "Ka" | "Kd" | "Ks" | "Ke" | "Ns" | "d" | "Tr" | "illum"
+        ) || TEXTURE_MAP_STATEMENTS.contains(&statement);
+        if !is_known_statement {
+            continue;
+        }
+        let material = current_material
+            .as_mut()
+            .ok_or_else(|| line_error(&format!("{} given before any newmtl", statement)))?;
+        let arguments: Vec<&str> = parts.collect();
+
+        let color = || parse_color(&arguments).map_err(|error| line_error(&error));
+        match statement {
+            "Ka" => material.ka = color()?.unwrap_or(material.ka),
+            "Kd" => material.kd = color()?.or(material.kd),
+            "Ks" => material.ks = color()?.unwrap_or(material.ks),
+            "Ke" => material.ke = color()?.unwrap_or(material.ke),
+            "Ns" => {
+                material.ns = parse_number(&arguments)
+                    .filter(|ns| *ns >= 0.0)
+                    .ok_or_else(|| line_error("Unable to process specular exponent"))?
+            }
+            "d" => {
+                // The halo option fades the material at glancing angles, which isn't supported
+                let arguments = match arguments.first() {
+                    Some(&"-halo") => &arguments[1..],
+                    _ => &arguments[..],
+                };
+                material.dissolve = parse_number(arguments)
+                    .filter(|d| (0.0..=1.0).contains(d))
+                    .ok_or_else(|| line_error("Unable to process dissolve"))?
+            }
+            "Tr" => {
+                material.dissolve = parse_number(&arguments)
+                    .filter(|tr| (0.0..=1.0).contains(tr))
+                    .map(|tr| 1.0 - tr)
+                    .ok_or_else(|| line_error("Unable to process transparency"))?
+            }
+            "illum" => {
+                material.illum = match arguments[..] {
+                    [illum] => illum.parse::<u32>().ok().filter(|illum| *illum <= 10),
+                    _ => None,
                 }
+                .ok_or_else(|| line_error("Unable to process illumination model"))?
+            }
+            _ => {
+                let file_name = arguments
+                    .last()
+                    .ok_or_else(|| line_error("Unable to process texture file name"))?;
+                material
+                    .texture_maps
+                    .insert(statement.to_string(), file_name.to_string());
             }
-            _ => {}
         }
     }
 
@@ -50,5 +143,143 @@ pub fn parse_materials(file_contents: &str) -> HashMap<String, Material> {
         materials.insert(material.name.clone(), material);
     }
 
-    materials
+    Ok(materials)
+}
+
+// Parses r g b, or a single value used for all three. Returns None for the spectral and xyz
+// forms, which aren't supported
+fn parse_color(arguments: &[&str]) -> Result<Option<(f32, f32, f32)>, String> {
+    if matches!(arguments.first(), Some(&("spectral" | "xyz"))) {
+        return Ok(None);
+    }
+
+    let values = arguments
+        .iter()
+        .map(|argument| {
+            argument
+                .parse::<f32>()
+                .ok()
+                .filter(|value| value.is_finite())
+        })
+        .collect::<Option<Vec<f32>>>()
+        .ok_or_else(|| format!("Invalid color {}", arguments.join(" ")))?;
+    match values[..] {
+        [value] => Ok(Some((value, value, value))),
+        [r, g, b] => Ok(Some((r, g, b))),
+        _ => Err(format!(
+            "Color has {} values, 1 or 3 are needed",
+            values.len()
+        )),
+    }
+}
+
+fn parse_number(arguments: &[&str]) -> Option<f32> {
+    match arguments {
+        [value] => value.parse::<f32>().ok().filter(|value| value.is_finite()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_material(file_contents: &str) -> Material {
+        let materials = parse_materials(file_contents).unwrap();
+        assert_eq!(materials.len(), 1);
+        materials.into_values().next().unwrap()
+    }
+
+    #[test]
+    fn statements_not_given_keep_the_format_defaults() {
+        let material = parse_material("newmtl plain\n");
+        assert_eq!(material.name, "plain");
+        assert_eq!(material.ka, (0.0, 0.0, 0.0));
+        assert_eq!(material.kd, None);
+        assert_eq!(material.ks, (0.0, 0.0, 0.0));
+        assert_eq!(material.ke, (0.0, 0.0, 0.0));
+        assert_eq!(material.ns, 0.0);
+        assert_eq!(material.dissolve, 1.0);
+        assert_eq!(material.illum, 0);
+        assert!(material.texture_maps.is_empty());
+    }
+
+    #[test]
+    fn colors_take_one_or_three_values() {
+        let material = parse_material("newmtl paint\nKa 0.5\nKd 1 0.5 0\nKe 2 -1 0.5\n");
+        assert_eq!(material.ka, (0.5, 0.5, 0.5));
+        assert_eq!(material.kd, Some((1.0, 0.5, 0.0)));
+        assert_eq!(material.ke, (2.0, -1.0, 0.5));
+    }
+
+    #[test]
+    fn unsupported_color_forms_keep_the_previous_color() {
+        let material = parse_material(
+            "newmtl paint\nKd 1 0 0\nKd spectral red.rfl 1\nKs xyz 0.5 0.5 0.5\nNi 1.5\n",
+        );
+        assert_eq!(material.kd, Some((1.0, 0.0, 0.0)));
+        assert_eq!(material.ks, (0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn malformed_lines_fail_with_the_line_number() {
+        let cases = [
+            ("Kd 1 red 0", "Line 2: Invalid color 1 red 0"),
+            ("Kd 1 0", "Line 2: Color has 2 values, 1 or 3 are needed"),
+            ("Kd", "Line 2: Color has 0 values, 1 or 3 are needed"),
+            ("Ns -1", "Line 2: Unable to process specular exponent"),
+            ("Ns", "Line 2: Unable to process specular exponent"),
+            ("d 1.5", "Line 2: Unable to process dissolve"),
+            ("d -halo", "Line 2: Unable to process dissolve"),
+            ("Tr -0.5", "Line 2: Unable to process transparency"),
+            ("illum 11", "Line 2: Unable to process illumination model"),
+            ("illum 1.5", "Line 2: Unable to process illumination model"),
+            ("map_Kd", "Line 2: Unable to process texture file name"),
+        ];
+        for (line, error) in cases {
+            let file_contents = format!("newmtl paint\n{}\n", line);
+            assert_eq!(parse_materials(&file_contents).unwrap_err(), error);
+        }
+    }
+
+    #[test]
+    fn transparency_sets_the_dissolve() {
+        let material = parse_material("newmtl glass\nTr 0.25\n");
+        assert_eq!(material.dissolve, 0.75);
+    }
+
+    #[test]
+    fn dissolve_skips_the_halo_option() {
+        let material = parse_material("newmtl glass\nd -halo 0.5\n");
+        assert_eq!(material.dissolve, 0.5);
+    }
+
+    #[test]
+    fn material_names_are_joined_with_spaces() {
+        let material = parse_material("newmtl car  paint\n");
+        assert_eq!(material.name, "car paint");
+        assert_eq!(
+            parse_materials("newmtl\n").unwrap_err(),
+            "Line 1: Unable to process material name"
+        );
+    }
+
+    #[test]
+    fn texture_maps_take_the_file_name_after_the_options() {
+        let material = parse_material(
+            "newmtl paint\nmap_Kd -s 2 2 1 -clamp on paint.png\nbump -bm 0.5 bumps.png\n",
+        );
+        assert_eq!(material.texture_maps["map_Kd"], "paint.png");
+        assert_eq!(material.texture_maps["bump"], "bumps.png");
+    }
+
+    #[test]
+    fn statements_before_newmtl_fail() {
+        assert_eq!(
+            parse_materials("# paint\nKd 1 0 0\nnewmtl paint\n").unwrap_err(),
+            "Line 2: Kd given before any newmtl"
+        );
+        // Statements the renderer has no use for are ignored, even before newmtl
+        assert!(parse_materials("Ni 1.5\nnewmtl paint\n").is_ok());
+    }
 }
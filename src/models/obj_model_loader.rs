@@ -1,142 +1,370 @@
-use crate::geometry::{Color, Model, Triangle3};
+use crate::geometry::{Color, MaterialRange, Model, SubMesh, Triangle3};
+use crate::material::{FallbackColor, FALLBACK_GRAY};
 use crate::models::model_store::MaterialStore;
 use crate::models::mtl_loader::Material;
 
 use nalgebra::{Point3, Vector3};
-use rand::Rng;
+use std::ops::Range;
 
-pub fn load_model(file_contents: &str, material_store: &MaterialStore) -> Model {
+// Name of the group faces belong to before any o or g line, and of groups given no name
+const DEFAULT_GROUP_NAME: &str = "default";
+
+// Vertex of a face, as indices into the vertex and normal lists
+#[derive(Copy, Clone, Debug)]
+struct FaceVertex {
+    vertex_index: usize,
+    normal_index: Option<usize>,
+}
+
+/*
+    Loads the common subset of the OBJ format: vertices, normals, faces of any number of vertices
+    in the v, v/vt, v//vn and v/vt/vn forms with positive or negative (relative) indices, materials,
+    and o objects and g groups, which become the model's sub meshes. Faces after a g line naming
+    several groups are members of each of them. Triangles without normals get the normal of their
+    face, faces without a material color get the fallback color, and faces of materials with an
+    emissive color (Ke) give off that light. Texture coordinates, smoothing groups, lines and
+    points are ignored.
+*/
+pub fn load_model(
+    file_contents: &str,
+    material_store: &MaterialStore,
+    fallback_color: FallbackColor,
+) -> Result<Model, String> {
     let mut material_file_names: Vec<&str> = Vec::new();
-    let mut vertices: Vec<(f32, f32, f32)> = Vec::new();
-    let mut normals: Vec<(f32, f32, f32)> = Vec::new();
+    let mut vertices: Vec<Point3<f64>> = Vec::new();
+    let mut normals: Vec<Vector3<f64>> = Vec::new();
+    // Texture coordinates aren't used, but faces referring to them are checked against the count
+    let mut texture_coordinate_count = 0;
     let mut current_material: Option<Material> = None;
 
     let mut triangles: Vec<Triangle3> = Vec::new();
+    let mut material_ranges: Vec<MaterialRange> = Vec::new();
+    let mut fallback_faces: Vec<Range<usize>> = Vec::new();
+    let mut sub_meshes: Vec<SubMesh> = vec![SubMesh {
+        name: DEFAULT_GROUP_NAME.to_string(),
+        triangles: 0..0,
+    }];
+    // Number of sub meshes at the end of the list that faces are currently added to
+    let mut open_sub_mesh_count = 1;
 
-    for line in file_contents.lines() {
+    for (line_index, line) in file_contents.lines().enumerate() {
         let line = line.trim();
+        let line_error = |message: &str| format!("Line {}: {}", line_index + 1, message);
 
         if line.is_empty() || line.starts_with('#') {
             continue;
         }
 
-        //println!("{:#?}", current_material);
-
         let mut parts = line.split_whitespace();
         match parts.next() {
             Some("mtllib") => {
-                material_file_names.push(parts.next().expect("Unable to process file name"))
+                let file_names: Vec<&str> = parts.collect();
+                if file_names.is_empty() {
+                    return Err(line_error("Unable to process file name"));
+                }
+                material_file_names.extend(file_names);
             }
             Some("usemtl") => {
-                let material_name = parts.next().expect("Unable to process material name");
+                // Material names may contain spaces, like in newmtl lines
+                let material_name = parts.collect::<Vec<&str>>().join(" ");
+                if material_name.is_empty() {
+                    return Err(line_error("Unable to process material name"));
+                }
                 let material: Material = material_file_names
                     .iter()
-                    .filter_map(|file_name| material_store.get(file_name, material_name))
+                    .filter_map(|file_name| material_store.get(file_name, &material_name))
                     .next()
-                    .expect(&format!("Unknown material with name {}", material_name))
+                    .ok_or_else(|| {
+                        line_error(&format!("Unknown material with name {}", material_name))
+                    })?
                     .clone();
 
+                // Faces from here on use the material, until the next usemtl
+                close_range(
+                    material_ranges
+                        .last_mut()
+                        .map(|material| &mut material.triangles),
+                    &triangles,
+                );
+                material_ranges.push(MaterialRange {
+                    name: material.name.clone(),
+                    triangles: triangles.len()..triangles.len(),
+                });
                 current_material.replace(material);
             }
-            Some("v") => vertices.push(parse_vertex(parts).expect("Unable to process vertex")),
-            Some("vn") => normals.push(parse_normal(parts).expect("Unable to process normal")),
+            Some(keyword @ ("o" | "g")) => {
+                let names: Vec<&str> = parts.collect();
+                // An object has one name, which may contain spaces, while a g line lists every
+                // group the faces that follow are members of
+                let names: Vec<String> = match (keyword, names.is_empty()) {
+                    ("o", true) => return Err(line_error("Unable to process object name")),
+                    ("o", false) => vec![names.join(" ")],
+                    (_, true) => vec![DEFAULT_GROUP_NAME.to_string()],
+                    (_, false) => names.iter().map(|name| name.to_string()).collect(),
+                };
+
+                // Faces from here on belong to the groups, until the next o or g
+                for sub_mesh in sub_meshes.iter_mut().rev().take(open_sub_mesh_count) {
+                    close_range(Some(&mut sub_mesh.triangles), &triangles);
+                }
+                open_sub_mesh_count = names.len();
+                sub_meshes.extend(names.into_iter().map(|name| SubMesh {
+                    name,
+                    triangles: triangles.len()..triangles.len(),
+                }));
+            }
+            Some("v") => vertices
+                .push(parse_vertex(parts).ok_or_else(|| line_error("Unable to process vertex"))?),
+            Some("vn") => normals
+                .push(parse_normal(parts).ok_or_else(|| line_error("Unable to process normal"))?),
+            Some("vt") => texture_coordinate_count += 1,
             Some("f") => {
-                if let Some(face_triangles) =
-                    parse_face(parts, current_material.clone(), &vertices, &normals)
+                let face_triangles = parse_face(
+                    parts,
+                    current_material.as_ref(),
+                    &vertices,
+                    &normals,
+                    texture_coordinate_count,
+                )
+                .map_err(|error| line_error(&format!("Unable to process face: {}", error)))?;
+                if current_material
+                    .as_ref()
+                    .and_then(get_material_color)
+                    .is_none()
                 {
-                    triangles.extend(face_triangles);
-                } else {
-                    panic!("Unable to process face");
+                    fallback_faces.push(triangles.len()..triangles.len() + face_triangles.len());
                 }
+                triangles.extend(face_triangles);
             }
             _ => {}
         }
     }
 
-    Model {
+    close_range(
+        material_ranges
+            .last_mut()
+            .map(|material| &mut material.triangles),
+        &triangles,
+    );
+    material_ranges.retain(|material| !material.triangles.is_empty());
+    for sub_mesh in sub_meshes.iter_mut().rev().take(open_sub_mesh_count) {
+        close_range(Some(&mut sub_mesh.triangles), &triangles);
+    }
+    sub_meshes.retain(|sub_mesh| !sub_mesh.triangles.is_empty());
+
+    let mut model = Model {
+        name: String::new(),
         geometry: triangles,
+        materials: material_ranges,
+        sub_meshes,
+        fallback_faces,
+    };
+    let fallback_colors = fallback_color.get_triangle_colors(&model);
+    for (triangle, color) in model.geometry.iter_mut().zip(fallback_colors) {
+        if let Some(color) = color {
+            triangle.color = color;
+        }
     }
+    Ok(model)
 }
 
-fn parse_vertex<'a>(
-    parts: impl Iterator<Item = &'a str> + std::fmt::Debug,
-) -> Option<(f32, f32, f32)> {
-    let mut coords = parts.filter_map(|p| p.parse::<f32>().ok());
-
-    Some((coords.next()?, coords.next()?, coords.next()?))
+fn get_material_color(material: &Material) -> Option<Color> {
+    material.kd.map(to_color)
 }
 
-fn parse_normal<'a>(parts: impl Iterator<Item = &'a str>) -> Option<(f32, f32, f32)> {
-    let mut coords = parts.filter_map(|p| p.parse::<f32>().ok());
-    Some((coords.next()?, coords.next()?, coords.next()?))
+// Converts a MTL color, clamping values outside of 0 to 1
+fn to_color(color: (f32, f32, f32)) -> Color {
+    Color::new(
+        (color.0 * 255.0).round() as u8,
+        (color.1 * 255.0).round() as u8,
+        (color.2 * 255.0).round() as u8,
+    )
 }
 
-fn parse_face<'a>(
-    parts: impl Iterator<Item = &'a str>,
-    current_material: Option<Material>,
-    vertices: &Vec<(f32, f32, f32)>,
-    normals: &Vec<(f32, f32, f32)>,
-) -> Option<Vec<Triangle3>> {
-    let vertex_data: Vec<&str> = parts.collect();
-    if vertex_data.len() < 3 {
-        return None;
+// Ends the material or group range at the triangles added so far
+fn close_range(range: Option<&mut Range<usize>>, triangles: &[Triangle3]) {
+    if let Some(range) = range {
+        range.end = triangles.len();
     }
+}
 
-    let mut parsed_tokens: Vec<(usize, usize)> = Vec::new();
-    for token in &vertex_data {
-        let parts: Vec<&str> = token.split('/').collect();
-        let v_index = parts.get(0)?.parse::<usize>().ok()?;
-        // Texture index is not supported
-        let n_index = parts.get(2)?.parse::<usize>().ok()?;
-        parsed_tokens.push((v_index, n_index));
-    }
+// Parses x, y and z, ignoring the optional w or vertex color that may follow
+fn parse_vertex<'a>(mut parts: impl Iterator<Item = &'a str>) -> Option<Point3<f64>> {
+    let mut next_coord = || parts.next()?.parse::<f64>().ok();
+    Some(Point3::new(next_coord()?, next_coord()?, next_coord()?))
+}
 
-    // Assume all indices share the share the same normal
-    let normal_index = parsed_tokens[0].1 - 1; // Convert 1-based index to 0-based.
-    let normal_tuple: (f32, f32, f32) = normals.get(normal_index)?.clone();
-    let computed_normal = Vector3::new(
-        normal_tuple.0 as f64,
-        normal_tuple.1 as f64,
-        normal_tuple.2 as f64,
-    );
+fn parse_normal<'a>(mut parts: impl Iterator<Item = &'a str>) -> Option<Vector3<f64>> {
+    let mut next_coord = || parts.next()?.parse::<f64>().ok();
+    Some(Vector3::new(next_coord()?, next_coord()?, next_coord()?))
+}
 
-    // Use color of material if it exists, otherwise use random color
-    let mut rng = rand::thread_rng();
-    let random_color = Color::new(rng.gen(), rng.gen(), rng.gen());
-    let color = current_material
-        .map(|material| {
-            let kd = material.kd.unwrap();
-            Color::new(
-                (kd.0 * 255.0).round() as u8,
-                (kd.1 * 255.0).round() as u8,
-                (kd.2 * 255.0).round() as u8,
+fn parse_face<'a>(
+    parts: impl Iterator<Item = &'a str>,
+    current_material: Option<&Material>,
+    vertices: &[Point3<f64>],
+    normals: &[Vector3<f64>],
+    texture_coordinate_count: usize,
+) -> Result<Vec<Triangle3>, String> {
+    let face_vertices = parts
+        .map(|token| {
+            parse_face_vertex(
+                token,
+                vertices.len(),
+                normals.len(),
+                texture_coordinate_count,
             )
         })
-        .unwrap_or(random_color);
+        .collect::<Result<Vec<FaceVertex>, String>>()?;
+    if face_vertices.len() < 3 {
+        return Err(format!(
+            "Face has {} vertices, at least 3 are needed",
+            face_vertices.len()
+        ));
+    }
+
+    // Use color of material if it exists, faces without one are given the fallback color later
+    let color = current_material
+        .and_then(get_material_color)
+        .unwrap_or(FALLBACK_GRAY);
+    let emission = current_material.map_or(Color::BLACK, |material| to_color(material.ke));
 
     // Fan triangulation:
     // For a face with vertices [v0, v1, v2, v3, ... vN],
     // produce triangles: [v0, v1, v2], [v0, v2, v3], ..., [v0, v_{N-1}, vN].
     let mut triangles: Vec<Triangle3> = Vec::new();
-    for i in 1..(parsed_tokens.len() - 1) {
-        let (v0_index, _) = parsed_tokens[0];
-        let (v1_index, _) = parsed_tokens[i];
-        let (v2_index, _) = parsed_tokens[i + 1];
+    for i in 1..(face_vertices.len() - 1) {
+        let triangle_vertices = [face_vertices[0], face_vertices[i], face_vertices[i + 1]];
+        let [p0, p1, p2] = triangle_vertices.map(|face_vertex| vertices[face_vertex.vertex_index]);
 
-        let v0 = vertices.get(v0_index - 1)?.clone();
-        let v1 = vertices.get(v1_index - 1)?.clone();
-        let v2 = vertices.get(v2_index - 1)?.clone();
-
-        let p0 = Point3::new(v0.0 as f64, v0.1 as f64, v0.2 as f64);
-        let p1 = Point3::new(v1.0 as f64, v1.1 as f64, v1.2 as f64);
-        let p2 = Point3::new(v2.0 as f64, v2.1 as f64, v2.2 as f64);
+        // The renderer shades each triangle with a single normal, so vertex normals are
+        // averaged. Without them, or when they cancel out, the face normal is used
+        let vertex_normals: Option<Vec<Vector3<f64>>> = triangle_vertices
+            .iter()
+            .map(|face_vertex| Some(normals[face_vertex.normal_index?]))
+            .collect();
+        let normal = vertex_normals
+            .and_then(|vertex_normals| {
+                vertex_normals
+                    .iter()
+                    .sum::<Vector3<f64>>()
+                    .try_normalize(0.0)
+            })
+            .or_else(|| (p1 - p0).cross(&(p2 - p0)).try_normalize(0.0))
+            .unwrap_or_else(Vector3::zeros);
 
         triangles.push(Triangle3 {
             vertices: [p0, p1, p2],
-            color: color.clone(),
-            normal: computed_normal, // Same normal for the entire face.
+            color,
+            emission,
+            normal,
         });
     }
 
-    Some(triangles)
+    Ok(triangles)
+}
+
+// Parses a v, v/vt, v//vn or v/vt/vn face vertex. Empty texture or normal indices are left out
+fn parse_face_vertex(
+    token: &str,
+    vertex_count: usize,
+    normal_count: usize,
+    texture_coordinate_count: usize,
+) -> Result<FaceVertex, String> {
+    let parts: Vec<&str> = token.split('/').collect();
+    if parts.len() > 3 {
+        return Err(format!("Invalid face vertex {}", token));
+    }
+
+    let vertex_index = resolve_index(parts[0], vertex_count, "vertex")?;
+    if let Some(texture_index) = parts.get(1).filter(|part| !part.is_empty()) {
+        resolve_index(
+            texture_index,
+            texture_coordinate_count,
+            "texture coordinate",
+        )?;
+    }
+    let normal_index = match parts.get(2).filter(|part| !part.is_empty()) {
+        Some(normal_index) => Some(resolve_index(normal_index, normal_count, "normal")?),
+        None => None,
+    };
+
+    Ok(FaceVertex {
+        vertex_index,
+        normal_index,
+    })
+}
+
+// Converts a 1-based index, or a negative index counting back from the last element defined so
+// far, into a 0-based index
+fn resolve_index(index: &str, count: usize, element: &str) -> Result<usize, String> {
+    let parsed_index = index
+        .parse::<isize>()
+        .map_err(|_| format!("Invalid {} index {}", element, index))?;
+
+    let resolved_index = match parsed_index {
+        1.. => Some(parsed_index as usize - 1),
+        0 => None,
+        _ => count.checked_sub(parsed_index.unsigned_abs()),
+    };
+    resolved_index
+        .filter(|resolved_index| *resolved_index < count)
+        .ok_or_else(|| format!("No {} {}, {} defined so far", element, index, count))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::mtl_loader::parse_materials;
+
+    #[test]
+    fn faces_belong_to_every_group_of_a_g_line() {
+        let model = load_model(
+            "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\ng wheel front\nf 1 2 3\nf 1 3 2\no Body Panel\nf 1 2 3\n",
+            &MaterialStore::new(),
+            FallbackColor::Gray,
+        )
+        .unwrap();
+
+        let sub_meshes: Vec<(&str, Range<usize>)> = model
+            .sub_meshes
+            .iter()
+            .map(|sub_mesh| (sub_mesh.name.as_str(), sub_mesh.triangles.clone()))
+            .collect();
+        assert_eq!(
+            sub_meshes,
+            vec![
+                ("default", 0..1),
+                ("wheel", 1..3),
+                ("front", 1..3),
+                ("Body Panel", 3..4),
+            ]
+        );
+        assert_eq!(
+            model.get_triangle_group_names(),
+            vec![
+                Some("default"),
+                Some("wheel"),
+                Some("wheel"),
+                Some("Body Panel")
+            ]
+        );
+    }
+
+    #[test]
+    fn usemtl_names_are_joined_with_spaces() {
+        let mut material_store = MaterialStore::new();
+        let materials = parse_materials("newmtl car paint\nKd 1 0 0\n").unwrap();
+        material_store.put("car.mtl", &materials);
+
+        let model = load_model(
+            "mtllib car.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nusemtl car  paint\nf 1 2 3\n",
+            &material_store,
+            FallbackColor::Gray,
+        )
+        .unwrap();
+
+        assert_eq!(model.get_material_name(0), Some("car paint"));
+        assert_eq!(model.geometry[0].color, Color::new(255, 0, 0));
+    }
 }
@@ -1,18 +1,87 @@
 use nalgebra::{Matrix4, Point2, Point3, Vector3};
+use serde::{Deserialize, Serialize};
 
 use crate::buffer::Buffer;
 use crate::camera::Camera;
-use crate::entity::Entity;
 use crate::geometry;
 use crate::light::Light;
+use crate::scene_graph::SceneGraph;
+
+// Pixels further than this from a triangle edge are skipped when rendering wireframes
+const WIREFRAME_LINE_WIDTH: f64 = 0.6;
+
+#[derive(Copy, Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RenderOptions {
+    pub wireframe: bool,
+}
+
+/*
+    The entity and triangle drawn at each pixel of a rendered frame, kept so that what is on
+    screen can be selected or described under the mouse.
+*/
+pub struct PickBuffer {
+    entity_indices: Buffer<Option<usize>>,
+    triangle_indices: Buffer<usize>,
+    depths: Buffer<f64>,
+    inverse_view_projection_matrix: Matrix4<f64>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PickResult {
+    // Index of the entity in the scene graph
+    pub entity_index: usize,
+    // Index of the triangle in the entity's model geometry
+    pub triangle_index: usize,
+    pub world_position: Point3<f64>,
+    // Normalized device depth, from -1 at the near plane to 1 at the far plane
+    pub depth: f64,
+}
+
+impl PickBuffer {
+    // A buffer with nothing drawn, for before the first frame is rendered
+    pub fn new(width: usize, height: usize) -> Self {
+        PickBuffer {
+            entity_indices: Buffer::new(None, width, height),
+            triangle_indices: Buffer::new(0, width, height),
+            depths: Buffer::new(f64::MAX, width, height),
+            inverse_view_projection_matrix: Matrix4::identity(),
+        }
+    }
+
+    // What was drawn at the pixel, or None for the background and pixels off the screen
+    pub fn pick(&self, x: usize, y: usize) -> Option<PickResult> {
+        let width = self.entity_indices.width;
+        let height = self.entity_indices.height;
+        if x >= width || y >= height {
+            return None;
+        }
+
+        let entity_index = self.entity_indices[y][x]?;
+        let depth = self.depths[y][x];
+        let pixel = Point3::new((x as f64) + 0.5, (y as f64) + 0.5, depth);
+        Some(PickResult {
+            entity_index,
+            triangle_index: self.triangle_indices[y][x],
+            world_position: screen_to_world(
+                &pixel,
+                &self.inverse_view_projection_matrix,
+                width,
+                height,
+            ),
+            depth,
+        })
+    }
+}
 
 pub fn render_scene(
     screen_buffer: &mut Buffer<[u8; 3]>,
-    entities: &[Entity],
+    scene_graph: &SceneGraph,
     world_lights: &[Light],
     camera: &dyn Camera,
     background_color: [u8; 3],
-) -> Vec<geometry::ProjectionResult> {
+    render_options: &RenderOptions,
+) -> PickBuffer {
     let view_projection_matrix: Matrix4<f64> = camera.get_view_projection_matrix();
     let inverse_view_projection_matrix = view_projection_matrix.try_inverse().unwrap();
 
@@ -21,55 +90,47 @@ pub fn render_scene(
     let mut z_buffer = Buffer::<f64>::new(f64::MAX, screen_width, screen_height);
     let mut projection_buffer = Buffer::<usize>::new(usize::MAX, screen_width, screen_height);
 
-    // Transform entity models to the world coordinate system
-    let geometry: Vec<geometry::Triangle3> = entities
-        .iter()
-        .flat_map(|entity| geometry::transform_entity_model(entity))
-        .collect();
-
-    let mut cached_projection_results = Vec::with_capacity(geometry.len());
+    let mut cached_projection_results = Vec::new();
+    // Entity and model triangle index each projection result came from, for picking
+    let mut cached_triangle_ids = Vec::new();
 
     // Vertex shader / processing
-    for triangle in geometry {
-        // world cords -> camera coords -> ndc -> screen coords
-        let projection_results = geometry::project_triangle(
-            &triangle,
-            &view_projection_matrix,
-            screen_width,
-            screen_height,
-        );
-
-        for projection_result in &projection_results {
-            cached_projection_results.push(*projection_result);
-            let projection_result_index = cached_projection_results.len() - 1;
-
-            let (x_min, y_min, x_max, y_max) = projection_result
-                .screen_bounding_box
-                .get_screen_constrained_bounds(screen_width, screen_height);
-
-            // Rasterize
-            for y in y_min..y_max {
-                for x in x_min..x_max {
-                    let px = (x as f64) + 0.5;
-                    let py = (y as f64) + 0.5;
-                    let pixel = Point2::new(px, py);
-
-                    if !geometry::is_point_in_triangle(&pixel, &projection_result.screen_triangle) {
-                        continue;
-                    }
-
-                    let z = geometry::interpolate_attributes_at_pixel(&pixel, projection_result);
-
-                    // pixel in this triangle is the closest to the camera
-                    if z < z_buffer[y][x] {
-                        z_buffer[y][x] = z;
-                        projection_buffer[y][x] = projection_result_index;
-                    }
-                }
+    for entity_index in 0..scene_graph.len() {
+        // Transform entity models to the world coordinate system
+        for (triangle_index, triangle) in scene_graph.get_indexed_world_triangles(entity_index) {
+            // world cords -> camera coords -> ndc -> screen coords
+            let projection_results = geometry::project_triangle(
+                &triangle,
+                &view_projection_matrix,
+                screen_width,
+                screen_height,
+            );
+
+            for projection_result in &projection_results {
+                cached_projection_results.push(*projection_result);
+                cached_triangle_ids.push((entity_index, triangle_index));
+                let projection_result_index = cached_projection_results.len() - 1;
+
+                rasterize_triangle(
+                    projection_result,
+                    screen_width,
+                    screen_height,
+                    render_options,
+                    |x, y, z| {
+                        // pixel in this triangle is the closest to the camera
+                        if z < z_buffer[y][x] {
+                            z_buffer[y][x] = z;
+                            projection_buffer[y][x] = projection_result_index;
+                        }
+                    },
+                );
             }
         }
     }
 
+    let mut pick_buffer = PickBuffer::new(screen_width, screen_height);
+    pick_buffer.inverse_view_projection_matrix = inverse_view_projection_matrix;
+
     // Pixel shader / deferred lighting pass
     for y in 0..screen_height {
         for x in 0..screen_width {
@@ -79,6 +140,9 @@ pub fn render_scene(
                 continue;
             }
             let projection_result = &cached_projection_results[projection_result_index];
+            let (entity_index, triangle_index) = cached_triangle_ids[projection_result_index];
+            pick_buffer.entity_indices[y][x] = Some(entity_index);
+            pick_buffer.triangle_indices[y][x] = triangle_index;
 
             let pixel = Point3::new((x as f64) + 0.5, (y as f64) + 0.5, z_buffer[y][x]);
 
@@ -91,16 +155,69 @@ pub fn render_scene(
                 screen_height,
             );
 
+            // Emissive triangles glow by the same amount whether they're lit or not
             let color = projection_result.screen_triangle.color;
-            let r = ((color.r as f64) * light_intensity) as u8;
-            let g = ((color.g as f64) * light_intensity) as u8;
-            let b = ((color.b as f64) * light_intensity) as u8;
+            let emission = projection_result.screen_triangle.emission;
+            let shade = |channel: u8, emission: u8| {
+                (((channel as f64) * light_intensity) as u8).saturating_add(emission)
+            };
+            let r = shade(color.r, emission.r);
+            let g = shade(color.g, emission.g);
+            let b = shade(color.b, emission.b);
 
             screen_buffer[y][x] = [r, g, b];
         }
     }
 
-    cached_projection_results.clone()
+    pick_buffer.depths = z_buffer;
+    pick_buffer
+}
+
+// Calls plot with the position and depth of every pixel the triangle covers
+fn rasterize_triangle(
+    projection_result: &geometry::ProjectionResult,
+    screen_width: usize,
+    screen_height: usize,
+    render_options: &RenderOptions,
+    mut plot: impl FnMut(usize, usize, f64),
+) {
+    let (x_min, y_min, x_max, y_max) = projection_result
+        .screen_bounding_box
+        .get_screen_constrained_bounds(screen_width, screen_height);
+
+    for y in y_min..y_max {
+        for x in x_min..x_max {
+            let px = (x as f64) + 0.5;
+            let py = (y as f64) + 0.5;
+            let pixel = Point2::new(px, py);
+
+            if !geometry::is_point_in_triangle(&pixel, &projection_result.screen_triangle) {
+                continue;
+            }
+
+            if render_options.wireframe
+                && geometry::distance_to_triangle_edges(&pixel, &projection_result.screen_triangle)
+                    > WIREFRAME_LINE_WIDTH
+            {
+                continue;
+            }
+
+            let z = geometry::interpolate_attributes_at_pixel(&pixel, projection_result);
+            plot(x, y, z);
+        }
+    }
+}
+
+// Unprojects a pixel at the given normalized device depth back into world space
+fn screen_to_world(
+    pixel: &Point3<f64>,
+    inverse_view_projection_matrix: &Matrix4<f64>,
+    screen_width: usize,
+    screen_height: usize,
+) -> Point3<f64> {
+    let p_ndc = geometry::screen_to_ndc(pixel, screen_width, screen_height).to_homogeneous();
+    let point_world_space_homogeneous = inverse_view_projection_matrix * p_ndc;
+    Point3::from(point_world_space_homogeneous.xyz() / point_world_space_homogeneous.w)
 }
 
 fn calculate_pixel_lighting(
@@ -111,9 +228,13 @@ fn calculate_pixel_lighting(
     screen_width: usize,
     screen_height: usize,
 ) -> f64 {
-    let p_ndc = geometry::screen_to_ndc(pixel, screen_width, screen_height).to_homogeneous();
-    let point_world_space_homogeneous = inverse_view_projection_matrix * p_ndc;
-    let point_world_space = point_world_space_homogeneous.xyz() / point_world_space_homogeneous.w;
+    let point_world_space = screen_to_world(
+        pixel,
+        inverse_view_projection_matrix,
+        screen_width,
+        screen_height,
+    )
+    .coords;
 
     let light_intensity = world_lights
         .iter()
@@ -191,9 +312,9 @@ fn calculate_dithered_pixel_value(
     let ge = calculate_error(g);
     let be = calculate_error(b);
 
-    let ra = r.min(255).max(0) as u8;
-    let ga = g.min(255).max(0) as u8;
-    let ba = b.min(255).max(0) as u8;
+    let ra = r.clamp(0, 255) as u8;
+    let ga = g.clamp(0, 255) as u8;
+    let ba = b.clamp(0, 255) as u8;
 
     let mut diffuse_error =
         |x: i16, y: i16, r_error: i16, g_error: i16, b_error: i16, factor: i16| {
@@ -0,0 +1,31 @@
+use crate::camera::Camera;
+use crate::light::Light;
+use crate::renderer::RenderOptions;
+use crate::scene_graph::SceneGraph;
+
+pub const DEFAULT_BACKGROUND_COLOR: [u8; 3] = [100, 100, 100];
+
+/*
+    Everything a scene file declares: the entity hierarchy, its lights, the cameras to view
+    it with, and how to render it. Cameras are in the order declared, followed by the
+    scene's camera path and follow cameras.
+*/
+pub struct Scene<'a> {
+    pub scene_graph: SceneGraph<'a>,
+    pub lights: Vec<Light>,
+    pub cameras: Vec<Box<dyn Camera>>,
+    pub background_color: [u8; 3],
+    pub render_options: RenderOptions,
+}
+
+impl<'a> Scene<'a> {
+    pub fn new(scene_graph: SceneGraph<'a>, lights: Vec<Light>) -> Self {
+        Scene {
+            scene_graph,
+            lights,
+            cameras: Vec::new(),
+            background_color: DEFAULT_BACKGROUND_COLOR,
+            render_options: RenderOptions::default(),
+        }
+    }
+}
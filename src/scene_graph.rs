@@ -0,0 +1,275 @@
+use nalgebra::{Matrix4, Point3, Rotation3};
+
+use crate::entity::Entity;
+use crate::geometry::{self, Color, Triangle3};
+use crate::material::{FallbackColor, MaterialOverride};
+
+/*
+    Hierarchy of scene entities, where each entity's transform is local to its parent.
+    Entities are stored in a flat list ordered so parents always come before their
+    children, which lets the world transforms be computed in a single pass:
+
+    world_transform = parent_world_transform * translation * rotation * scale
+*/
+pub struct SceneGraph<'a> {
+    entities: Vec<Box<dyn Entity + 'a>>,
+    parents: Vec<Option<usize>>,
+    world_transforms: Vec<Matrix4<f64>>,
+    world_rotations: Vec<Rotation3<f64>>,
+    material_overrides: Vec<Option<MaterialOverride>>,
+    // Recolors the faces without a material color of every model, unless overridden per entity
+    fallback_color: Option<FallbackColor>,
+    // Fallback color of each triangle of each entity's model, worked out when the entity, its
+    // material override or the fallback color is set instead of on every frame. None for
+    // entities whose model has no faces without a material color, or with no fallback color
+    fallback_colors: Vec<Option<Vec<Option<Color>>>>,
+}
+
+impl Default for SceneGraph<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> SceneGraph<'a> {
+    pub fn new() -> Self {
+        SceneGraph {
+            entities: Vec::new(),
+            parents: Vec::new(),
+            world_transforms: Vec::new(),
+            world_rotations: Vec::new(),
+            material_overrides: Vec::new(),
+            fallback_color: None,
+            fallback_colors: Vec::new(),
+        }
+    }
+
+    // Adds the entity as a child of an existing node, or as a root, returning its index
+    pub fn add(&mut self, entity: Box<dyn Entity + 'a>, parent: Option<usize>) -> usize {
+        if let Some(parent) = parent {
+            assert!(
+                parent < self.entities.len(),
+                "Scene graph parent {} does not exist",
+                parent
+            );
+        }
+
+        let index = self.entities.len();
+        self.entities.push(entity);
+        self.parents.push(parent);
+        self.world_transforms.push(Matrix4::identity());
+        self.world_rotations.push(Rotation3::identity());
+        self.material_overrides.push(None);
+        self.fallback_colors.push(None);
+        self.update_world_transform(index);
+        self.update_fallback_colors(index);
+        index
+    }
+
+    // Removes the entity along with its descendants. Entities after it move down to fill the gap,
+    // so indices held from before no longer match
+    pub fn remove(&mut self, index: usize) {
+        // Parents come before their children, so one pass finds every descendant
+        let mut is_removed = vec![false; self.entities.len()];
+        for node in index..self.entities.len() {
+            is_removed[node] =
+                node == index || self.parents[node].is_some_and(|parent| is_removed[parent]);
+        }
+        let new_indices: Vec<usize> = is_removed
+            .iter()
+            .scan(0, |next_index, is_removed| {
+                let new_index = *next_index;
+                if !is_removed {
+                    *next_index += 1;
+                }
+                Some(new_index)
+            })
+            .collect();
+
+        remove_marked(&mut self.entities, &is_removed);
+        remove_marked(&mut self.parents, &is_removed);
+        remove_marked(&mut self.world_transforms, &is_removed);
+        remove_marked(&mut self.world_rotations, &is_removed);
+        remove_marked(&mut self.material_overrides, &is_removed);
+        remove_marked(&mut self.fallback_colors, &is_removed);
+
+        for parent in self.parents.iter_mut().flatten() {
+            *parent = new_indices[*parent];
+        }
+    }
+
+    // Advances every entity's animation, then propagates the new transforms to children
+    pub fn update(&mut self, delta_time: f64) {
+        for entity in &mut self.entities {
+            entity.update(delta_time);
+        }
+        self.update_world_transforms();
+    }
+
+    // Tells the entities where the camera is, updating the transforms of entities facing it
+    pub fn set_view_position(&mut self, view_position: Point3<f64>) {
+        for index in 0..self.entities.len() {
+            let local_view_position = match self.parents[index] {
+                Some(parent) => self.world_transforms[parent]
+                    .try_inverse()
+                    .map(|inverse| inverse.transform_point(&view_position))
+                    .unwrap_or(view_position),
+                None => view_position,
+            };
+            self.entities[index].set_view_position(local_view_position);
+            self.update_world_transform(index);
+        }
+    }
+
+    // Must be called after changing an entity's transform outside of update
+    pub fn update_world_transforms(&mut self) {
+        for index in 0..self.entities.len() {
+            self.update_world_transform(index);
+        }
+    }
+
+    fn update_world_transform(&mut self, index: usize) {
+        let entity = &self.entities[index];
+        let (parent_transform, parent_rotation) = match self.parents[index] {
+            Some(parent) => (self.world_transforms[parent], self.world_rotations[parent]),
+            None => (Matrix4::identity(), Rotation3::identity()),
+        };
+
+        self.world_transforms[index] = parent_transform * entity.get_local_transform();
+        self.world_rotations[index] = parent_rotation * entity.get_rotation();
+    }
+
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    pub fn get_entities(&self) -> &[Box<dyn Entity + 'a>] {
+        &self.entities
+    }
+
+    pub fn get_entity(&self, index: usize) -> &(dyn Entity + 'a) {
+        self.entities[index].as_ref()
+    }
+
+    // Call update_world_transforms after changing the entity's transform
+    pub fn get_entity_mut(&mut self, index: usize) -> &mut (dyn Entity + 'a) {
+        self.entities[index].as_mut()
+    }
+
+    pub fn get_parent(&self, index: usize) -> Option<usize> {
+        self.parents[index]
+    }
+
+    pub fn get_children(&self, index: usize) -> Vec<usize> {
+        (0..self.parents.len())
+            .filter(|child| self.parents[*child] == Some(index))
+            .collect()
+    }
+
+    pub fn get_world_transform(&self, index: usize) -> Matrix4<f64> {
+        self.world_transforms[index]
+    }
+
+    pub fn get_world_origin(&self, index: usize) -> Point3<f64> {
+        self.world_transforms[index].transform_point(&Point3::origin())
+    }
+
+    // Rotation of the entity in world space, without the scaling of it or its parents
+    pub fn get_world_rotation(&self, index: usize) -> Rotation3<f64> {
+        self.world_rotations[index]
+    }
+
+    // Looks up an entity by the id given to it in the scene file
+    pub fn find_entity(&self, id: &str) -> Option<usize> {
+        self.entities
+            .iter()
+            .position(|entity| entity.get_id() == Some(id))
+    }
+
+    // Changes the colors and visibility of the entity's model, without affecting its children
+    pub fn set_material_override(
+        &mut self,
+        index: usize,
+        material_override: Option<MaterialOverride>,
+    ) {
+        self.material_overrides[index] = material_override;
+        self.update_fallback_colors(index);
+    }
+
+    pub fn get_material_override(&self, index: usize) -> Option<&MaterialOverride> {
+        self.material_overrides[index].as_ref()
+    }
+
+    pub fn set_fallback_color(&mut self, fallback_color: Option<FallbackColor>) {
+        self.fallback_color = fallback_color;
+        for index in 0..self.len() {
+            self.update_fallback_colors(index);
+        }
+    }
+
+    pub fn get_fallback_color(&self) -> Option<FallbackColor> {
+        self.fallback_color
+    }
+
+    pub fn get_world_triangles(&self, index: usize) -> Vec<Triangle3> {
+        self.get_indexed_world_triangles(index)
+            .into_iter()
+            .map(|(_, triangle)| triangle)
+            .collect()
+    }
+
+    // The entity's world triangles along with their index in its model's geometry, which
+    // skips the triangles of hidden materials
+    pub fn get_indexed_world_triangles(&self, index: usize) -> Vec<(usize, Triangle3)> {
+        let entity = self.get_entity(index);
+        let mut triangles: Vec<(usize, Triangle3)> =
+            geometry::transform_entity_model(entity, &self.world_transforms[index])
+                .into_iter()
+                .enumerate()
+                .collect();
+
+        // Entities with a color of their own override every face's color anyway
+        let fallback_colors = self.fallback_colors[index]
+            .as_ref()
+            .filter(|_| entity.get_maybe_color().is_none());
+        if let Some(fallback_colors) = fallback_colors {
+            for (triangle_index, triangle) in &mut triangles {
+                if let Some(color) = fallback_colors[*triangle_index] {
+                    triangle.color = color;
+                }
+            }
+        }
+
+        match self.material_overrides[index].as_ref() {
+            Some(material_override) => material_override.apply(entity.get_model(), triangles),
+            None => triangles,
+        }
+    }
+
+    fn update_fallback_colors(&mut self, index: usize) {
+        let model = self.entities[index].get_model();
+        let fallback_color = self.material_overrides[index]
+            .as_ref()
+            .and_then(|material_override| material_override.fallback_color)
+            .or(self.fallback_color)
+            .filter(|_| !model.fallback_faces.is_empty());
+        self.fallback_colors[index] =
+            fallback_color.map(|fallback_color| fallback_color.get_triangle_colors(model));
+    }
+
+    // Every triangle in the scene in world space
+    pub fn get_all_world_triangles(&self) -> Vec<Triangle3> {
+        (0..self.len())
+            .flat_map(|index| self.get_world_triangles(index))
+            .collect()
+    }
+}
+
+fn remove_marked<T>(values: &mut Vec<T>, is_removed: &[bool]) {
+    let mut is_removed = is_removed.iter();
+    values.retain(|_| is_removed.next() != Some(&true));
+}
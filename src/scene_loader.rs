@@ -1,52 +1,159 @@
-use crate::entity;
+use crate::animation::{AnimatedEntity, Animation};
+use crate::behavior::{Behavior, BehaviorEntity};
+use crate::entity::{Entity, EntityDescription, EntityRegistry, Group, Transform};
 use crate::geometry::Color;
 use crate::light;
+use crate::material::{FallbackColor, MaterialOverride};
 use crate::models::ModelStore;
-use nalgebra::{Matrix4, Point3, Rotation3, Unit, Vector3};
+use crate::renderer::RenderOptions;
+use crate::scene::Scene;
+use crate::scene_graph::SceneGraph;
+use nalgebra::{Matrix4, Point3, Quaternion, Rotation3, Unit, UnitQuaternion, Vector3};
+use serde::de::DeserializeOwned;
 use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::Path;
+
+mod cameras;
+mod export;
+pub use export::cameras_to_json;
+pub use export::replace_cameras;
+pub use export::scene_to_json;
+pub use export::to_json_string;
+mod migration;
+pub use migration::migrate;
+pub use migration::SCENE_VERSION;
+
+/*
+    A problem with a scene file, located by a path to the offending value such as
+    "objects[2].children[0].rotation". The path is empty for problems with the whole file.
+*/
+#[derive(Clone, Debug, PartialEq)]
+pub struct SceneError {
+    pub path: String,
+    pub message: String,
+}
+
+impl SceneError {
+    pub fn new(path: &str, message: &str) -> Self {
+        SceneError {
+            path: path.to_string(),
+            message: message.to_string(),
+        }
+    }
+
+    // Moves the field named by a serde unknown or missing field error into the path
+    pub(crate) fn from_serde(path: &str, message: &str) -> Self {
+        let field = ["unknown field `", "missing field `"]
+            .iter()
+            .find_map(|prefix| message.strip_prefix(prefix))
+            .and_then(|rest| rest.split('`').next());
+
+        match field {
+            Some(field) => SceneError::new(&join_path(path, field), message),
+            None => SceneError::new(path, message),
+        }
+    }
+
+    // Locates a problem relative to the value containing it, such as an included file in the
+    // including scene
+    fn prefixed(self, path: &str) -> Self {
+        if self.path.is_empty() {
+            return SceneError::new(path, &self.message);
+        }
+        SceneError::new(&join_path(path, &self.path), &self.message)
+    }
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "{}: {}", self.path, self.message)
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+fn join_path(path: &str, field: &str) -> String {
+    if path.is_empty() {
+        field.to_string()
+    } else {
+        format!("{}.{}", path, field)
+    }
+}
+
+// The fields of an object read by the scene loader, the rest are passed to its entity factory
+const OBJECT_FIELDS: [&str; 9] = [
+    "type",
+    "id",
+    "position",
+    "rotation",
+    "scale",
+    "animation",
+    "behaviors",
+    "material",
+    "children",
+];
+
+// Directory holding the scene files, which include paths are relative to
+pub const SCENE_PATH: &str = "scenes";
+
+// Reads the contents of a scene file named in the includes of a scene
+pub type IncludeReader<'r> = dyn Fn(&str) -> Result<String, String> + 'r;
+
+// Type name of objects instancing a prefab, which is handled by the loader instead of the registry
+pub const PREFAB_TYPE: &str = "Prefab";
 
 #[derive(Deserialize, Debug)]
-#[allow(dead_code)]
-pub struct JsonWorldData {
-    objects: Vec<JsonObject>,
-    lights: Vec<JsonLight>,
+#[serde(deny_unknown_fields)]
+struct JsonWorldData {
+    #[allow(dead_code)]
+    version: u64,
+    // Scene files whose objects, lights and prefabs are added to this scene
+    #[serde(default)]
+    includes: Vec<String>,
+    #[serde(default)]
+    prefabs: serde_json::Map<String, serde_json::Value>,
+    // Entities are created by the EntityRegistry from the type name of each object
+    #[serde(default)]
+    objects: Vec<serde_json::Value>,
+    #[serde(default)]
+    lights: Vec<serde_json::Value>,
+    #[serde(default)]
+    cameras: Vec<serde_json::Value>,
+    camera_path: Option<serde_json::Value>,
+    #[serde(default)]
+    follow_cameras: Vec<serde_json::Value>,
+    background: Option<serde_json::Value>,
+    render_options: Option<serde_json::Value>,
+    fallback_color: Option<serde_json::Value>,
+}
+
+// A named group of objects and lights, which objects of the Prefab type place in the scene
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+struct JsonPrefab {
+    #[serde(default)]
+    objects: Vec<serde_json::Value>,
+    #[serde(default)]
+    lights: Vec<serde_json::Value>,
 }
 
 #[derive(Deserialize, Debug)]
-#[serde(tag = "type")]
-enum JsonObject {
-    Square {
-        model: String,
-        origin: [f64; 3],
-        rotation_axis: [f64; 3],
-        rotation_angle: f64,
-        scale: f64,
-    },
-    Rectangle {
-        model: String,
-        origin: [f64; 3],
-        rotation_axis: [f64; 3],
-        rotation_angle: f64,
-        width: f64,
-        height: f64,
-        color: [u8; 3],
-    },
-    SpinningObject {
-        model: String,
-        origin: [f64; 3],
-        rotation: [f64; 3],
-        rotation_axis: [f64; 3],
-        rotation_angle: f64,
-        angular_velocity: f64,
-        scale: f64,
-    },
+#[serde(deny_unknown_fields)]
+struct JsonPrefabInstance {
+    prefab: String,
 }
 
 #[derive(Deserialize, Debug)]
-#[serde(tag = "type")]
+#[serde(tag = "type", deny_unknown_fields)]
 enum JsonLight {
     PointLight {
-        origin: [f64; 3],
+        position: [f64; 3],
         intensity: f64,
         linear_attenuation: f64,
         quadratic_attenuation: f64,
@@ -58,107 +165,629 @@ enum JsonLight {
     },
 }
 
-pub fn load_scene<'a>(
-    json_string: &'a str,
+// Rotations are given as Euler angles about x, y and z, an [x, y, z, w] quaternion, or an axis
+// and angle, all in radians
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+enum JsonRotation {
+    Euler([f64; 3]),
+    Quaternion([f64; 4]),
+    AxisAngle { axis: [f64; 3], angle: f64 },
+}
+
+impl JsonRotation {
+    fn to_rotation(&self) -> Result<Rotation3<f64>, String> {
+        match self {
+            JsonRotation::Euler(angles) => {
+                if !angles.iter().all(|angle| angle.is_finite()) {
+                    return Err("Euler angles must be finite".to_string());
+                }
+                Ok(Rotation3::from_euler_angles(
+                    angles[0], angles[1], angles[2],
+                ))
+            }
+            JsonRotation::Quaternion([x, y, z, w]) => {
+                let quaternion = Quaternion::new(*w, *x, *y, *z);
+                if quaternion.norm() == 0.0 {
+                    return Err("Quaternion must not be zero".to_string());
+                }
+                Ok(UnitQuaternion::from_quaternion(quaternion).to_rotation_matrix())
+            }
+            JsonRotation::AxisAngle { axis, angle } => {
+                let axis = Unit::try_new(Vector3::from(*axis), 0.0)
+                    .ok_or_else(|| "Rotation axis must not be zero".to_string())?;
+                Ok(Rotation3::from_axis_angle(&axis, *angle))
+            }
+        }
+    }
+}
+
+// Scale is either uniform or given per axis
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum JsonScale {
+    Uniform(f64),
+    NonUniform([f64; 3]),
+}
+
+// Parses the scene file, migrating it from older versions of the format
+fn parse_world_data(json_string: &str) -> Result<JsonWorldData, SceneError> {
+    let json: serde_json::Value = serde_json::from_str(json_string)
+        .map_err(|error| SceneError::new("", &format!("Invalid JSON: {}", error)))?;
+    let json = migrate(json)?;
+
+    JsonWorldData::deserialize(&json)
+        .map_err(|error| SceneError::from_serde("", &error.to_string()))
+}
+
+fn parse_value<T: DeserializeOwned>(
+    value: &serde_json::Value,
+    path: &str,
+) -> Result<T, SceneError> {
+    T::deserialize(value).map_err(|error| SceneError::from_serde(path, &error.to_string()))
+}
+
+// Parses an optional field of a scene file object
+fn parse_field<T: DeserializeOwned>(
+    object: &serde_json::Map<String, serde_json::Value>,
+    field: &str,
+    path: &str,
+) -> Result<Option<T>, SceneError> {
+    object
+        .get(field)
+        .map(|value| parse_value(value, &join_path(path, field)))
+        .transpose()
+}
+
+pub fn load_scene<'a>(json_string: &str, model_store: &'a ModelStore) -> Scene<'a> {
+    load_scene_with_registry(json_string, model_store, &EntityRegistry::default())
+}
+
+pub fn try_load_scene<'a>(
+    json_string: &str,
     model_store: &'a ModelStore,
-) -> (Vec<entity::Entity<'a>>, Vec<light::Light>) {
-    let json_world_data: JsonWorldData = serde_json::from_str(json_string)
-        .unwrap_or_else(|_| panic!("Failed to deserialize json {}", json_string));
+) -> Result<Scene<'a>, SceneError> {
+    try_load_scene_with_registry(json_string, model_store, &EntityRegistry::default())
+}
+
+// Loads the scene, creating its entities with the factories registered in the registry
+pub fn load_scene_with_registry<'a>(
+    json_string: &str,
+    model_store: &'a ModelStore,
+    registry: &EntityRegistry,
+) -> Scene<'a> {
+    try_load_scene_with_registry(json_string, model_store, registry)
+        .unwrap_or_else(|error| panic!("Failed to load scene: {}", error))
+}
+
+// Loads the scene, returning the first problem found in the scene file instead of panicking.
+// Included scene files are read relative to the scenes directory
+pub fn try_load_scene_with_registry<'a>(
+    json_string: &str,
+    model_store: &'a ModelStore,
+    registry: &EntityRegistry,
+) -> Result<Scene<'a>, SceneError> {
+    try_load_scene_with_includes(json_string, model_store, registry, &|path| {
+        std::fs::read_to_string(Path::new(SCENE_PATH).join(path)).map_err(|error| error.to_string())
+    })
+}
+
+// Loads the scene, reading the scene files it includes with read_include
+pub fn try_load_scene_with_includes<'a>(
+    json_string: &str,
+    model_store: &'a ModelStore,
+    registry: &EntityRegistry,
+    read_include: &IncludeReader,
+) -> Result<Scene<'a>, SceneError> {
+    let json_world_data = parse_world_data(json_string)?;
+
+    // Included files come first, depth first, so the scene's own objects are added last
+    let mut sources = Vec::new();
+    read_includes(
+        &json_world_data,
+        "",
+        read_include,
+        &mut Vec::new(),
+        &mut HashSet::new(),
+        &mut sources,
+    )?;
+    sources.push(SceneSource {
+        world_data: json_world_data,
+        path: String::new(),
+    });
 
-    let objects = json_world_data
-        .objects
+    let mut prefabs = HashMap::new();
+    for source in &sources {
+        for (name, prefab) in &source.world_data.prefabs {
+            let prefab_path = join_path(&source.path, &format!("prefabs.{}", name));
+            let prefab: JsonPrefab = parse_value(prefab, &prefab_path)?;
+            if prefabs.contains_key(name) {
+                return Err(SceneError::new(
+                    &prefab_path,
+                    &format!("Duplicate prefab {}", name),
+                ));
+            }
+            prefabs.insert(name.clone(), (prefab, prefab_path));
+        }
+    }
+
+    let mut object_loader = ObjectLoader {
+        registry,
+        model_store,
+        prefabs,
+        instanced_prefabs: Vec::new(),
+        scene_graph: SceneGraph::new(),
+        lights: Vec::new(),
+    };
+    for source in &sources {
+        object_loader.add_objects(
+            &source.world_data.objects,
+            None,
+            &join_path(&source.path, "objects"),
+            None,
+        )?;
+        object_loader.add_lights(
+            &source.world_data.lights,
+            &join_path(&source.path, "lights"),
+            &Matrix4::identity(),
+        )?;
+    }
+    let ObjectLoader {
+        scene_graph,
+        lights,
+        ..
+    } = object_loader;
+
+    // Ids are used to look entities up by name, so they must be unique within the scene
+    let mut ids = HashSet::new();
+    for id in scene_graph
+        .get_entities()
         .iter()
-        .map(|object| match object {
-            JsonObject::Square {
-                model,
-                origin,
-                rotation_axis,
-                rotation_angle,
-                scale,
-            } => entity::Entity::Square(entity::Square {
-                model: model_store.get_model(model),
-                origin: Point3::<f64>::new(origin[0], origin[1], origin[2]),
-                rotation: Rotation3::<f64>::from_axis_angle(
-                    &Unit::new_normalize(Vector3::new(
-                        rotation_axis[0],
-                        rotation_axis[1],
-                        rotation_axis[2],
-                    )),
-                    *rotation_angle,
-                ),
-                scale: Matrix4::<f64>::new_scaling(*scale),
-            }),
-            JsonObject::Rectangle {
-                model,
-                origin,
-                rotation_axis,
-                rotation_angle,
-                width,
-                height,
-                color,
-            } => entity::Entity::Rectangle(entity::Rectangle {
-                model: model_store.get_model(model),
-                origin: Point3::<f64>::new(origin[0], origin[1], origin[2]),
-                rotation: Rotation3::<f64>::from_axis_angle(
-                    &Unit::new_normalize(Vector3::new(
-                        rotation_axis[0],
-                        rotation_axis[1],
-                        rotation_axis[2],
-                    )),
-                    *rotation_angle,
-                ),
-                scale: Matrix4::<f64>::new_nonuniform_scaling(&Vector3::new(*width, *height, 1.0)),
-                color: Color::new(color[0], color[1], color[2]),
-            }),
-            JsonObject::SpinningObject {
-                model,
-                origin,
-                rotation,
-                rotation_axis,
-                rotation_angle,
-                angular_velocity,
-                scale,
-            } => entity::Entity::SpinningObject(entity::SpinningObject {
-                model: model_store.get_model(model),
-                origin: Point3::<f64>::new(origin[0], origin[1], origin[2]),
-                rotation: Rotation3::from_euler_angles(rotation[0], rotation[1], rotation[2]),
-                rotation_axis: Vector3::<f64>::new(
-                    rotation_axis[0],
-                    rotation_axis[1],
-                    rotation_axis[2],
+        .filter_map(|entity| entity.get_id())
+    {
+        if !ids.insert(id) {
+            return Err(SceneError::new(
+                "objects",
+                &format!("Duplicate entity id {}", id),
+            ));
+        }
+    }
+
+    // Only the including scene's cameras and settings are used, not those of included files
+    let json_world_data = &sources.last().unwrap().world_data;
+    let mut scene = Scene::new(scene_graph, lights);
+    scene.cameras = cameras::load_cameras(
+        &json_world_data.cameras,
+        json_world_data.camera_path.as_ref(),
+        &json_world_data.follow_cameras,
+        &scene.scene_graph,
+    )?;
+    if let Some(background) = &json_world_data.background {
+        scene.background_color = parse_value(background, "background")?;
+    }
+    if let Some(render_options) = &json_world_data.render_options {
+        scene.render_options = parse_value::<RenderOptions>(render_options, "render_options")?;
+    }
+    if let Some(fallback_color) = &json_world_data.fallback_color {
+        let fallback_color = parse_value::<FallbackColor>(fallback_color, "fallback_color")?;
+        scene.scene_graph.set_fallback_color(Some(fallback_color));
+    }
+
+    Ok(scene)
+}
+
+// A scene file and the path its problems are reported under, such as "includes[0]"
+struct SceneSource {
+    world_data: JsonWorldData,
+    path: String,
+}
+
+// Reads the files included by the scene file, and the files they include in turn. A file
+// included more than once, such as by two files that both include it, is only read the first
+// time, so its prefabs and objects are added once
+fn read_includes(
+    world_data: &JsonWorldData,
+    path: &str,
+    read_include: &IncludeReader,
+    include_stack: &mut Vec<String>,
+    loaded_includes: &mut HashSet<String>,
+    sources: &mut Vec<SceneSource>,
+) -> Result<(), SceneError> {
+    for (index, include) in world_data.includes.iter().enumerate() {
+        let include_path = join_path(path, &format!("includes[{}]", index));
+        if include_stack.contains(include) {
+            return Err(SceneError::new(
+                &include_path,
+                &format!("{} includes itself", include),
+            ));
+        }
+        if !loaded_includes.insert(include.clone()) {
+            continue;
+        }
+
+        let json_string = read_include(include).map_err(|error| {
+            SceneError::new(
+                &include_path,
+                &format!("Failed to read {}: {}", include, error),
+            )
+        })?;
+        let included_world_data =
+            parse_world_data(&json_string).map_err(|error| error.prefixed(&include_path))?;
+
+        include_stack.push(include.clone());
+        read_includes(
+            &included_world_data,
+            &include_path,
+            read_include,
+            include_stack,
+            loaded_includes,
+            sources,
+        )?;
+        include_stack.pop();
+
+        sources.push(SceneSource {
+            world_data: included_world_data,
+            path: include_path,
+        });
+    }
+    Ok(())
+}
+
+// Builds the scene graph and lights from the objects of the scene and its included files
+struct ObjectLoader<'r, 'a> {
+    registry: &'r EntityRegistry,
+    model_store: &'a ModelStore<'a>,
+    prefabs: HashMap<String, (JsonPrefab, String)>,
+    // Prefabs being instanced, innermost last, to catch prefabs that instance themselves
+    instanced_prefabs: Vec<String>,
+    scene_graph: SceneGraph<'a>,
+    lights: Vec<light::Light>,
+}
+
+impl<'r, 'a> ObjectLoader<'r, 'a> {
+    /*
+        Adds the objects and their nested children depth first, so parents precede children.
+        Ids of objects inside a prefab instance with an id are prefixed by the instance's id,
+        so "lamp" inside the instance "left_desk" becomes "left_desk.lamp".
+    */
+    fn add_objects(
+        &mut self,
+        objects: &[serde_json::Value],
+        parent: Option<usize>,
+        path: &str,
+        id_prefix: Option<&str>,
+    ) -> Result<(), SceneError> {
+        for (index, object) in objects.iter().enumerate() {
+            let object_path = format!("{}[{}]", path, index);
+            let fields = object
+                .as_object()
+                .ok_or_else(|| SceneError::new(&object_path, "Object must be a JSON object"))?;
+
+            let type_path = join_path(&object_path, "type");
+            let type_name: String = parse_field(fields, "type", &object_path)?
+                .ok_or_else(|| SceneError::new(&type_path, "Missing type"))?;
+            if type_name != PREFAB_TYPE && !self.registry.is_registered(&type_name) {
+                return Err(SceneError::new(
+                    &type_path,
+                    &format!("Unknown entity type {}", type_name),
+                ));
+            }
+
+            let rotation = parse_field::<JsonRotation>(fields, "rotation", &object_path)?
+                .map(|rotation| rotation.to_rotation())
+                .transpose()
+                .map_err(|error| SceneError::new(&join_path(&object_path, "rotation"), &error))?;
+            let scale = parse_field::<JsonScale>(fields, "scale", &object_path)
+                .map_err(|error| {
+                    SceneError::new(&error.path, "Scale must be a number or an [x, y, z] array")
+                })?
+                .map(|scale| match scale {
+                    JsonScale::Uniform(scale) => Vector3::new(scale, scale, scale),
+                    JsonScale::NonUniform(scale) => Vector3::from(scale),
+                });
+            let default_transform = Transform::new();
+            let transform = Transform {
+                position: parse_field::<[f64; 3]>(fields, "position", &object_path)?
+                    .map(Point3::from)
+                    .unwrap_or(default_transform.position),
+                rotation: rotation.unwrap_or(default_transform.rotation),
+                scale: scale.unwrap_or(default_transform.scale),
+            };
+
+            let id = parse_field::<String>(fields, "id", &object_path)?.map(|id| match id_prefix {
+                Some(id_prefix) => format!("{}.{}", id_prefix, id),
+                None => id,
+            });
+            let description = EntityDescription {
+                id: id.clone(),
+                transform,
+                properties: serde_json::Value::Object(
+                    fields
+                        .iter()
+                        .filter(|(field, _)| !OBJECT_FIELDS.contains(&field.as_str()))
+                        .map(|(field, value)| (field.clone(), value.clone()))
+                        .collect(),
                 ),
-                rotation_angle: *rotation_angle,
-                rotation_velocity: *angular_velocity,
-                scale: Matrix4::<f64>::new_scaling(*scale),
-            }),
-        })
-        .collect();
-
-    let lights = json_world_data
-        .lights
-        .iter()
-        .map(|light| match light {
-            JsonLight::PointLight {
-                origin,
-                intensity,
-                linear_attenuation,
-                quadratic_attenuation,
-                color,
-            } => light::Light::PointLight(light::PointLight {
-                origin: Point3::<f64>::new(origin[0], origin[1], origin[2]),
-                intensity: *intensity,
-                linear_attenuation: *linear_attenuation,
-                quadratic_attenuation: *quadratic_attenuation,
-                color: Color::new(color[0], color[1], color[2]),
-            }),
-            JsonLight::AmbientLight { intensity, color } => {
-                light::Light::AmbientLight(light::AmbientLight {
-                    intensity: *intensity,
-                    color: Color::new(color[0], color[1], color[2]),
-                })
+            };
+
+            // A prefab instance is an empty group, with the prefab's objects as its children
+            let prefab_name = if type_name == PREFAB_TYPE {
+                let instance: JsonPrefabInstance =
+                    parse_value(&description.properties, &object_path)?;
+                if !self.prefabs.contains_key(&instance.prefab) {
+                    return Err(SceneError::new(
+                        &join_path(&object_path, "prefab"),
+                        &format!("Unknown prefab {}", instance.prefab),
+                    ));
+                }
+                if self.instanced_prefabs.contains(&instance.prefab) {
+                    return Err(SceneError::new(
+                        &join_path(&object_path, "prefab"),
+                        &format!("Prefab {} instances itself", instance.prefab),
+                    ));
+                }
+                Some(instance.prefab)
+            } else {
+                None
+            };
+
+            let mut entity: Box<dyn Entity + 'a> = match prefab_name {
+                Some(_) => Box::new(Group {
+                    id: description.id.clone(),
+                    origin: transform.position,
+                    rotation: transform.rotation,
+                    rotation_axis: Vector3::y(),
+                    rotation_angle: 0.0,
+                    rotation_velocity: 0.0,
+                    scale: transform.get_scale_matrix(),
+                }),
+                None => self
+                    .registry
+                    .create(&type_name, &description, self.model_store)
+                    .map_err(|error| SceneError::from_serde(&object_path, &error))?,
+            };
+
+            // Any entity can be animated on top of its own behavior
+            if let Some(animation) = fields.get("animation") {
+                let animation = Animation::from_json(animation)
+                    .map_err(|error| error.prefixed(&join_path(&object_path, "animation")))?;
+                entity = Box::new(AnimatedEntity::new(entity, animation));
+            }
+
+            if let Some(behaviors) =
+                parse_field::<Vec<serde_json::Value>>(fields, "behaviors", &object_path)?
+            {
+                let behaviors = behaviors
+                    .iter()
+                    .enumerate()
+                    .map(|(behavior_index, behavior)| {
+                        let behavior_path =
+                            format!("{}.behaviors[{}]", object_path, behavior_index);
+                        let behavior: Behavior = parse_value(behavior, &behavior_path)?;
+                        behavior
+                            .validate()
+                            .map_err(|error| SceneError::new(&behavior_path, &error))?;
+                        Ok(behavior)
+                    })
+                    .collect::<Result<Vec<Behavior>, SceneError>>()?;
+                entity = Box::new(BehaviorEntity::new(entity, behaviors));
             }
-        })
-        .collect();
 
-    (objects, lights)
+            let node = self.scene_graph.add(entity, parent);
+
+            if let Some(material_override) =
+                parse_field::<MaterialOverride>(fields, "material", &object_path)?
+            {
+                let model = self.scene_graph.get_entity(node).get_model();
+                if let [first, ..] = material_override.get_unknown_materials(model).as_slice() {
+                    return Err(SceneError::new(
+                        &join_path(&object_path, "material"),
+                        &format!("Unknown material {}", first),
+                    ));
+                }
+                self.scene_graph
+                    .set_material_override(node, Some(material_override));
+            }
+
+            if let Some(prefab_name) = prefab_name {
+                // Cloned, since the map can't stay borrowed while the objects are added
+                let (prefab, prefab_path) = self.prefabs[&prefab_name].clone();
+                self.instanced_prefabs.push(prefab_name);
+                self.add_objects(
+                    &prefab.objects,
+                    Some(node),
+                    &join_path(&prefab_path, "objects"),
+                    id.as_deref().or(id_prefix),
+                )?;
+                let world_transform = self.scene_graph.get_world_transform(node);
+                self.add_lights(
+                    &prefab.lights,
+                    &join_path(&prefab_path, "lights"),
+                    &world_transform,
+                )?;
+                self.instanced_prefabs.pop();
+            }
+
+            if let Some(children) = fields.get("children") {
+                let children_path = join_path(&object_path, "children");
+                let children = children
+                    .as_array()
+                    .ok_or_else(|| SceneError::new(&children_path, "Children must be an array"))?;
+                self.add_objects(children, Some(node), &children_path, id_prefix)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // Point lights are placed by the transform, which is fixed when the scene is loaded
+    fn add_lights(
+        &mut self,
+        lights: &[serde_json::Value],
+        path: &str,
+        transform: &Matrix4<f64>,
+    ) -> Result<(), SceneError> {
+        for (index, light) in lights.iter().enumerate() {
+            let light: JsonLight = parse_value(light, &format!("{}[{}]", path, index))?;
+            self.lights.push(match light {
+                JsonLight::PointLight {
+                    position,
+                    intensity,
+                    linear_attenuation,
+                    quadratic_attenuation,
+                    color,
+                } => light::Light::PointLight(light::PointLight {
+                    origin: transform.transform_point(&Point3::from(position)),
+                    intensity,
+                    linear_attenuation,
+                    quadratic_attenuation,
+                    color: Color::new(color[0], color[1], color[2]),
+                }),
+                JsonLight::AmbientLight { intensity, color } => {
+                    light::Light::AmbientLight(light::AmbientLight {
+                        intensity,
+                        color: Color::new(color[0], color[1], color[2]),
+                    })
+                }
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use include_dir::{include_dir, Dir};
+
+    static MODEL_DIR: Dir = include_dir!("$CARGO_MANIFEST_DIR/models");
+
+    fn load_model_store() -> ModelStore<'static> {
+        let mut model_store = ModelStore::new(&MODEL_DIR);
+        model_store.init();
+        model_store
+    }
+
+    fn load_with_files<'a>(
+        json_string: &str,
+        model_store: &'a ModelStore,
+        files: &[(&str, &str)],
+    ) -> Result<Scene<'a>, SceneError> {
+        let files: HashMap<&str, &str> = files.iter().copied().collect();
+        try_load_scene_with_includes(
+            json_string,
+            model_store,
+            &EntityRegistry::default(),
+            &|path| {
+                files
+                    .get(path)
+                    .map(|contents| contents.to_string())
+                    .ok_or_else(|| "not found".to_string())
+            },
+        )
+    }
+
+    #[test]
+    fn loads_a_file_included_twice_once() {
+        let model_store = load_model_store();
+        let shared = r#"{
+            "version": 2,
+            "prefabs": {
+                "lamp": { "lights": [{ "type": "AmbientLight", "intensity": 0.5, "color": [255, 255, 255] }] }
+            },
+            "objects": [{ "type": "Square", "model": "cube.json", "id": "shared_cube" }]
+        }"#;
+        let left = r#"{ "version": 2, "includes": ["shared.json"] }"#;
+        let right = r#"{ "version": 2, "includes": ["shared.json"] }"#;
+        let scene = load_with_files(
+            r#"{
+                "version": 2,
+                "includes": ["left.json", "right.json"],
+                "objects": [{ "type": "Prefab", "prefab": "lamp" }]
+            }"#,
+            &model_store,
+            &[
+                ("shared.json", shared),
+                ("left.json", left),
+                ("right.json", right),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(scene.scene_graph.find_entity("shared_cube"), Some(0));
+        // The shared cube and the prefab instance's group
+        assert_eq!(scene.scene_graph.len(), 2);
+        assert_eq!(scene.lights.len(), 1);
+    }
+
+    #[test]
+    fn rejects_include_cycles() {
+        let model_store = load_model_store();
+        let error = load_with_files(
+            r#"{ "version": 2, "includes": ["a.json"] }"#,
+            &model_store,
+            &[
+                ("a.json", r#"{ "version": 2, "includes": ["b.json"] }"#),
+                ("b.json", r#"{ "version": 2, "includes": ["a.json"] }"#),
+            ],
+        )
+        .err()
+        .unwrap();
+
+        assert_eq!(error.path, "includes[0].includes[0].includes[0]");
+        assert_eq!(error.message, "a.json includes itself");
+    }
+
+    #[test]
+    fn rejects_zero_spin_axes() {
+        let model_store = load_model_store();
+        for object in [
+            r#"{ "type": "SpinningObject", "model": "cube.json", "spin_axis": [0, 0, 0], "angular_velocity": 1.0 }"#,
+            r#"{ "type": "Group", "spin_axis": [0, 0, 0] }"#,
+        ] {
+            let json_string = format!(r#"{{ "version": 2, "objects": [{}] }}"#, object);
+            let error = try_load_scene(&json_string, &model_store).err().unwrap();
+
+            assert_eq!(error.path, "objects[0]");
+            assert_eq!(
+                error.message,
+                "spin_axis must be a finite, non-zero vector, got [0.0, 0.0, 0.0]"
+            );
+        }
+    }
+
+    #[test]
+    fn locates_animation_errors_by_track_and_keyframe() {
+        let model_store = load_model_store();
+        let error = try_load_scene(
+            r#"{
+                "version": 2,
+                "objects": [{
+                    "type": "Square",
+                    "model": "cube.json",
+                    "animation": { "rotation": [{ "time": 0.0, "axis": [0, 0, 0], "angle": 1.0 }] }
+                }]
+            }"#,
+            &model_store,
+        )
+        .err()
+        .unwrap();
+
+        assert_eq!(error.path, "objects[0].animation.rotation[0]");
+    }
+
+    #[test]
+    fn rejects_rotations_that_are_not_finite() {
+        // JSON numbers are always finite, but values built in code may not be
+        assert_eq!(
+            JsonRotation::Euler([0.0, f64::INFINITY, 0.0]).to_rotation(),
+            Err("Euler angles must be finite".to_string())
+        );
+        assert!(JsonRotation::AxisAngle {
+            axis: [f64::NAN, 0.0, 0.0],
+            angle: 1.0
+        }
+        .to_rotation()
+        .is_err());
+    }
 }
@@ -0,0 +1,494 @@
+use nalgebra::{Point3, Vector3};
+use serde::Deserialize;
+
+use super::{join_path, parse_value, SceneError};
+use crate::camera::{
+    Camera, CameraKeyframe, ControllablePerspectiveCameraBuilder, FollowMode,
+    FollowPerspectiveCameraBuilder, OrbitControlPerspectiveCameraBuilder,
+    OrbitingPerspectiveCameraBuilder, OrthographicCameraBuilder, OrthographicPreset,
+    PathInterpolation, PathPerspectiveCameraBuilder, StaticPerspectiveCameraBuilder,
+};
+use crate::interpolation::{Easing, PlaybackMode};
+use crate::scene_graph::SceneGraph;
+
+/*
+    Cameras declared in the scene file. Fields left out keep the defaults of the camera's builder.
+    The path and follow types aren't listed, since they take the fields of the camera_path and
+    follow_cameras sections and are parsed as those.
+*/
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum JsonCamera {
+    Controllable {
+        position: Option<[f64; 3]>,
+        yaw: Option<f64>,
+        pitch: Option<f64>,
+        roll: Option<f64>,
+        fov: Option<f64>,
+        near_plane: Option<f64>,
+        far_plane: Option<f64>,
+        linear_speed: Option<f64>,
+        angular_speed: Option<f64>,
+        mouse_sensitivity: Option<f64>,
+        zoom_speed: Option<f64>,
+        walk_mode: Option<bool>,
+    },
+    OrbitControl {
+        target: Option<[f64; 3]>,
+        distance: Option<f64>,
+        yaw: Option<f64>,
+        pitch: Option<f64>,
+        fov: Option<f64>,
+        near_plane: Option<f64>,
+        far_plane: Option<f64>,
+        angular_speed: Option<f64>,
+        mouse_sensitivity: Option<f64>,
+        zoom_speed: Option<f64>,
+        min_distance: Option<f64>,
+        max_distance: Option<f64>,
+    },
+    Orbiting {
+        position: Option<[f64; 3]>,
+        yaw: Option<f64>,
+        pitch: Option<f64>,
+        fov: Option<f64>,
+        near_plane: Option<f64>,
+        far_plane: Option<f64>,
+        angular_speed: Option<f64>,
+    },
+    Static {
+        position: Option<[f64; 3]>,
+        yaw: Option<f64>,
+        pitch: Option<f64>,
+        fov: Option<f64>,
+        near_plane: Option<f64>,
+        far_plane: Option<f64>,
+    },
+    Orthographic {
+        position: Option<[f64; 3]>,
+        target: Option<[f64; 3]>,
+        yaw: Option<f64>,
+        pitch: Option<f64>,
+        view_height: Option<f64>,
+        near_plane: Option<f64>,
+        far_plane: Option<f64>,
+        mouse_sensitivity: Option<f64>,
+        zoom_speed: Option<f64>,
+        preset: Option<OrthographicPreset>,
+    },
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "mode", rename_all = "snake_case", deny_unknown_fields)]
+enum JsonFollowCamera {
+    Chase {
+        entity: String,
+        offset: [f64; 3],
+        #[serde(default = "default_keyframe_fov")]
+        fov: f64,
+        near_plane: Option<f64>,
+        far_plane: Option<f64>,
+    },
+    Track {
+        entity: String,
+        position: [f64; 3],
+        #[serde(default = "default_keyframe_fov")]
+        fov: f64,
+        near_plane: Option<f64>,
+        far_plane: Option<f64>,
+    },
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct JsonCameraPath {
+    #[serde(default)]
+    interpolation: PathInterpolation,
+    #[serde(default)]
+    easing: Easing,
+    #[serde(default)]
+    playback: PlaybackMode,
+    near_plane: Option<f64>,
+    far_plane: Option<f64>,
+    keyframes: Vec<JsonCameraKeyframe>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct JsonCameraKeyframe {
+    time: f64,
+    position: [f64; 3],
+    target: [f64; 3],
+    #[serde(default = "default_keyframe_fov")]
+    fov: f64,
+    #[serde(default)]
+    roll: f64,
+}
+
+fn default_keyframe_fov() -> f64 {
+    1.0
+}
+
+// Builds the declared cameras, then the camera path and the cameras following entities
+pub(super) fn load_cameras(
+    cameras: &[serde_json::Value],
+    camera_path: Option<&serde_json::Value>,
+    follow_cameras: &[serde_json::Value],
+    scene_graph: &SceneGraph,
+) -> Result<Vec<Box<dyn Camera>>, SceneError> {
+    let mut loaded_cameras: Vec<Box<dyn Camera>> = Vec::new();
+
+    for (index, camera) in cameras.iter().enumerate() {
+        let path = format!("cameras[{}]", index);
+        let camera = match camera.get("type").and_then(serde_json::Value::as_str) {
+            Some("path") => load_camera_path(&without_type(camera), &path)?,
+            Some("follow") => load_follow_camera(&without_type(camera), &path, scene_graph)?,
+            _ => build_camera(parse_value(camera, &path)?),
+        };
+        loaded_cameras.push(camera);
+    }
+
+    if let Some(camera_path) = camera_path {
+        loaded_cameras.push(load_camera_path(camera_path, "camera_path")?);
+    }
+
+    for (index, follow_camera) in follow_cameras.iter().enumerate() {
+        let path = format!("follow_cameras[{}]", index);
+        loaded_cameras.push(load_follow_camera(follow_camera, &path, scene_graph)?);
+    }
+
+    Ok(loaded_cameras)
+}
+
+fn without_type(camera: &serde_json::Value) -> serde_json::Value {
+    let mut camera = camera.clone();
+    if let Some(fields) = camera.as_object_mut() {
+        fields.remove("type");
+    }
+    camera
+}
+
+fn build_camera(camera: JsonCamera) -> Box<dyn Camera> {
+    match camera {
+        JsonCamera::Controllable {
+            position,
+            yaw,
+            pitch,
+            roll,
+            fov,
+            near_plane,
+            far_plane,
+            linear_speed,
+            angular_speed,
+            mouse_sensitivity,
+            zoom_speed,
+            walk_mode,
+        } => {
+            let mut builder = ControllablePerspectiveCameraBuilder::new();
+            if let Some(position) = position {
+                builder.origin(Point3::from(position));
+            }
+            if let Some(yaw) = yaw {
+                builder.yaw(yaw);
+            }
+            if let Some(pitch) = pitch {
+                builder.pitch(pitch);
+            }
+            if let Some(roll) = roll {
+                builder.roll(roll);
+            }
+            if let Some(fov) = fov {
+                builder.fov(fov);
+            }
+            if let Some(near_plane) = near_plane {
+                builder.near_plane(near_plane);
+            }
+            if let Some(far_plane) = far_plane {
+                builder.far_plane(far_plane);
+            }
+            if let Some(linear_speed) = linear_speed {
+                builder.linear_speed(linear_speed);
+            }
+            if let Some(angular_speed) = angular_speed {
+                builder.angular_speed(angular_speed);
+            }
+            if let Some(mouse_sensitivity) = mouse_sensitivity {
+                builder.mouse_sensitivity(mouse_sensitivity);
+            }
+            if let Some(zoom_speed) = zoom_speed {
+                builder.zoom_speed(zoom_speed);
+            }
+            if let Some(walk_mode) = walk_mode {
+                builder.walk_mode(walk_mode);
+            }
+            Box::new(builder.build())
+        }
+        JsonCamera::OrbitControl {
+            target,
+            distance,
+            yaw,
+            pitch,
+            fov,
+            near_plane,
+            far_plane,
+            angular_speed,
+            mouse_sensitivity,
+            zoom_speed,
+            min_distance,
+            max_distance,
+        } => {
+            let mut builder = OrbitControlPerspectiveCameraBuilder::new();
+            if let Some(target) = target {
+                builder.target(Point3::from(target));
+            }
+            if let Some(distance) = distance {
+                builder.distance(distance);
+            }
+            if let Some(yaw) = yaw {
+                builder.yaw(yaw);
+            }
+            if let Some(pitch) = pitch {
+                builder.pitch(pitch);
+            }
+            if let Some(fov) = fov {
+                builder.fov(fov);
+            }
+            if let Some(near_plane) = near_plane {
+                builder.near_plane(near_plane);
+            }
+            if let Some(far_plane) = far_plane {
+                builder.far_plane(far_plane);
+            }
+            if let Some(angular_speed) = angular_speed {
+                builder.angular_speed(angular_speed);
+            }
+            if let Some(mouse_sensitivity) = mouse_sensitivity {
+                builder.mouse_sensitivity(mouse_sensitivity);
+            }
+            if let Some(zoom_speed) = zoom_speed {
+                builder.zoom_speed(zoom_speed);
+            }
+            if let Some(min_distance) = min_distance {
+                builder.min_distance(min_distance);
+            }
+            if let Some(max_distance) = max_distance {
+                builder.max_distance(max_distance);
+            }
+            Box::new(builder.build())
+        }
+        JsonCamera::Orbiting {
+            position,
+            yaw,
+            pitch,
+            fov,
+            near_plane,
+            far_plane,
+            angular_speed,
+        } => {
+            let mut builder = OrbitingPerspectiveCameraBuilder::new();
+            if let Some(position) = position {
+                builder.origin(Point3::from(position));
+            }
+            if let Some(yaw) = yaw {
+                builder.yaw(yaw);
+            }
+            if let Some(pitch) = pitch {
+                builder.pitch(pitch);
+            }
+            if let Some(fov) = fov {
+                builder.fov(fov);
+            }
+            if let Some(near_plane) = near_plane {
+                builder.near_plane(near_plane);
+            }
+            if let Some(far_plane) = far_plane {
+                builder.far_plane(far_plane);
+            }
+            if let Some(angular_speed) = angular_speed {
+                builder.angular_speed(angular_speed);
+            }
+            Box::new(builder.build())
+        }
+        JsonCamera::Static {
+            position,
+            yaw,
+            pitch,
+            fov,
+            near_plane,
+            far_plane,
+        } => {
+            let mut builder = StaticPerspectiveCameraBuilder::new();
+            if let Some(position) = position {
+                builder.origin(Point3::from(position));
+            }
+            if let Some(yaw) = yaw {
+                builder.yaw(yaw);
+            }
+            if let Some(pitch) = pitch {
+                builder.pitch(pitch);
+            }
+            if let Some(fov) = fov {
+                builder.fov(fov);
+            }
+            if let Some(near_plane) = near_plane {
+                builder.near_plane(near_plane);
+            }
+            if let Some(far_plane) = far_plane {
+                builder.far_plane(far_plane);
+            }
+            Box::new(builder.build())
+        }
+        JsonCamera::Orthographic {
+            position,
+            target,
+            yaw,
+            pitch,
+            view_height,
+            near_plane,
+            far_plane,
+            mouse_sensitivity,
+            zoom_speed,
+            preset,
+        } => {
+            let mut builder = OrthographicCameraBuilder::new();
+            if let Some(position) = position {
+                builder.origin(Point3::from(position));
+            }
+            if let Some(target) = target {
+                builder.target(Point3::from(target));
+            }
+            if let Some(yaw) = yaw {
+                builder.yaw(yaw);
+            }
+            if let Some(pitch) = pitch {
+                builder.pitch(pitch);
+            }
+            if let Some(view_height) = view_height {
+                builder.view_height(view_height);
+            }
+            if let Some(near_plane) = near_plane {
+                builder.near_plane(near_plane);
+            }
+            if let Some(far_plane) = far_plane {
+                builder.far_plane(far_plane);
+            }
+            if let Some(mouse_sensitivity) = mouse_sensitivity {
+                builder.mouse_sensitivity(mouse_sensitivity);
+            }
+            if let Some(zoom_speed) = zoom_speed {
+                builder.zoom_speed(zoom_speed);
+            }
+            if let Some(preset) = preset {
+                builder.preset(preset);
+            }
+            Box::new(builder.build())
+        }
+    }
+}
+
+// Builds a camera flythrough, defined by the scene's camera_path or a camera of the path type
+fn load_camera_path(
+    camera_path: &serde_json::Value,
+    path: &str,
+) -> Result<Box<dyn Camera>, SceneError> {
+    let camera_path: JsonCameraPath = parse_value(camera_path, path)?;
+    if camera_path.keyframes.is_empty() {
+        return Err(SceneError::new(
+            &join_path(path, "keyframes"),
+            "Camera path requires a keyframe",
+        ));
+    }
+    if let Some(index) = camera_path
+        .keyframes
+        .iter()
+        .position(|keyframe| keyframe.position == keyframe.target)
+    {
+        return Err(SceneError::new(
+            &format!("{}.keyframes[{}]", path, index),
+            "Keyframe target must differ from its position",
+        ));
+    }
+
+    let keyframes = camera_path
+        .keyframes
+        .iter()
+        .map(|keyframe| CameraKeyframe {
+            time: keyframe.time,
+            position: Point3::from(keyframe.position),
+            target: Point3::from(keyframe.target),
+            fov: keyframe.fov,
+            roll: keyframe.roll,
+        })
+        .collect();
+
+    let mut builder = PathPerspectiveCameraBuilder::new();
+    builder
+        .keyframes(keyframes)
+        .interpolation(camera_path.interpolation)
+        .easing(camera_path.easing)
+        .playback_mode(camera_path.playback);
+    if let Some(near_plane) = camera_path.near_plane {
+        builder.near_plane(near_plane);
+    }
+    if let Some(far_plane) = camera_path.far_plane {
+        builder.far_plane(far_plane);
+    }
+    Ok(Box::new(builder.build()))
+}
+
+// Builds a camera following an entity, which must be in the scene
+fn load_follow_camera(
+    follow_camera: &serde_json::Value,
+    path: &str,
+    scene_graph: &SceneGraph,
+) -> Result<Box<dyn Camera>, SceneError> {
+    let (entity_id, mode, fov, near_plane, far_plane) = match parse_value(follow_camera, path)? {
+        JsonFollowCamera::Chase {
+            entity,
+            offset,
+            fov,
+            near_plane,
+            far_plane,
+        } => (
+            entity,
+            FollowMode::Chase {
+                offset: Vector3::from(offset),
+            },
+            fov,
+            near_plane,
+            far_plane,
+        ),
+        JsonFollowCamera::Track {
+            entity,
+            position,
+            fov,
+            near_plane,
+            far_plane,
+        } => (
+            entity,
+            FollowMode::Track {
+                position: Point3::from(position),
+            },
+            fov,
+            near_plane,
+            far_plane,
+        ),
+    };
+
+    if scene_graph.find_entity(&entity_id).is_none() {
+        return Err(SceneError::new(
+            &join_path(path, "entity"),
+            &format!("Entity {} not found in scene", entity_id),
+        ));
+    }
+
+    let mut builder = FollowPerspectiveCameraBuilder::new();
+    builder.entity_id(&entity_id).mode(mode).fov(fov);
+    if let Some(near_plane) = near_plane {
+        builder.near_plane(near_plane);
+    }
+    if let Some(far_plane) = far_plane {
+        builder.far_plane(far_plane);
+    }
+    Ok(Box::new(builder.build()))
+}
@@ -0,0 +1,391 @@
+use serde_json::{json, Map, Value};
+
+use super::{migrate, SceneError, SCENE_VERSION};
+use crate::camera::Camera;
+use crate::light::Light;
+use crate::scene::{Scene, DEFAULT_BACKGROUND_COLOR};
+use crate::scene_graph::SceneGraph;
+
+// Arrays of numbers are always kept on one line, other values only if they fit in this width
+const MAX_LINE_WIDTH: usize = 100;
+
+// Fields written first, in this order, followed by any others in alphabetical order and then
+// the fields holding nested objects
+const LEADING_FIELDS: [&str; 34] = [
+    "version",
+    "includes",
+    "prefabs",
+    "cameras",
+    "camera_path",
+    "follow_cameras",
+    "background",
+    "render_options",
+    "type",
+    "mode",
+    "id",
+    "entity",
+    "prefab",
+    "model",
+    "time",
+    "position",
+    "target",
+    "offset",
+    "value",
+    "axis",
+    "angle",
+    "rotation",
+    "scale",
+    "distance",
+    "yaw",
+    "pitch",
+    "roll",
+    "fov",
+    "view_height",
+    "near_plane",
+    "far_plane",
+    "color",
+    "spin_axis",
+    "angular_velocity",
+];
+const TRAILING_FIELDS: [&str; 7] = [
+    "animation",
+    "behaviors",
+    "material",
+    "objects",
+    "lights",
+    "keyframes",
+    "children",
+];
+
+/*
+    Describes the scene as a scene file of the current version, which loads back into the same
+    scene. Included files and prefab instances were expanded when the scene was loaded, so their
+    objects and lights are written out in place. Animations, spins and camera paths start again
+    from the beginning when the file is loaded.
+*/
+pub fn scene_to_json(scene: &Scene) -> Result<Value, SceneError> {
+    let scene_graph = &scene.scene_graph;
+    let objects = (0..scene_graph.len())
+        .filter(|index| scene_graph.get_parent(*index).is_none())
+        .enumerate()
+        .map(|(root_index, index)| {
+            object_to_json(scene_graph, index, &format!("objects[{}]", root_index))
+        })
+        .collect::<Result<Vec<Value>, SceneError>>()?;
+
+    let mut json = Map::new();
+    json.insert("version".to_string(), json!(SCENE_VERSION));
+    json.insert(
+        "cameras".to_string(),
+        Value::Array(cameras_to_json(&scene.cameras)?),
+    );
+    if scene.background_color != DEFAULT_BACKGROUND_COLOR {
+        json.insert("background".to_string(), json!(scene.background_color));
+    }
+    json.insert("render_options".to_string(), json!(scene.render_options));
+    if let Some(fallback_color) = scene_graph.get_fallback_color() {
+        json.insert("fallback_color".to_string(), json!(fallback_color));
+    }
+    json.insert("objects".to_string(), Value::Array(objects));
+    json.insert(
+        "lights".to_string(),
+        Value::Array(scene.lights.iter().map(light_to_json).collect()),
+    );
+    Ok(Value::Object(json))
+}
+
+pub fn cameras_to_json(cameras: &[Box<dyn Camera>]) -> Result<Vec<Value>, SceneError> {
+    cameras
+        .iter()
+        .enumerate()
+        .map(|(index, camera)| {
+            camera.to_json().ok_or_else(|| {
+                SceneError::new(
+                    &format!("cameras[{}]", index),
+                    "Camera has no scene file description",
+                )
+            })
+        })
+        .collect()
+}
+
+/*
+    Replaces the cameras of a scene file with the given cameras, keeping the rest of the file as
+    it is. The camera path and follow cameras are removed, as the cameras include them. Files of
+    an older version are migrated to the current one, and the version they were migrated from is
+    returned along with the file so the change can be pointed out.
+*/
+pub fn replace_cameras(
+    json_string: &str,
+    cameras: &[Box<dyn Camera>],
+) -> Result<(String, Option<u64>), SceneError> {
+    let json: Value = serde_json::from_str(json_string)
+        .map_err(|error| SceneError::new("", &format!("Invalid JSON: {}", error)))?;
+    // Files without a version are version 1, invalid versions are reported by the migration
+    let version = json.get("version").map_or(Some(1), Value::as_u64);
+    let mut json = migrate(json)?;
+    let fields = json
+        .as_object_mut()
+        .ok_or_else(|| SceneError::new("", "Scene must be a JSON object"))?;
+
+    fields.insert(
+        "cameras".to_string(),
+        Value::Array(cameras_to_json(cameras)?),
+    );
+    fields.remove("camera_path");
+    fields.remove("follow_cameras");
+    let migrated_from = version.filter(|version| *version < SCENE_VERSION);
+    Ok((to_json_string(&json), migrated_from))
+}
+
+// The object and its descendants, which follow it in the scene graph
+fn object_to_json(scene_graph: &SceneGraph, index: usize, path: &str) -> Result<Value, SceneError> {
+    let no_description = || SceneError::new(path, "Entity has no scene file description");
+    let mut json = scene_graph
+        .get_entity(index)
+        .to_json()
+        .ok_or_else(no_description)?;
+    let fields = json.as_object_mut().ok_or_else(no_description)?;
+
+    if let Some(material_override) = scene_graph.get_material_override(index) {
+        fields.insert("material".to_string(), json!(material_override));
+    }
+
+    let children = scene_graph
+        .get_children(index)
+        .into_iter()
+        .enumerate()
+        .map(|(child_index, child)| {
+            let child_path = format!("{}.children[{}]", path, child_index);
+            object_to_json(scene_graph, child, &child_path)
+        })
+        .collect::<Result<Vec<Value>, SceneError>>()?;
+    if !children.is_empty() {
+        fields.insert("children".to_string(), Value::Array(children));
+    }
+    Ok(json)
+}
+
+fn light_to_json(light: &Light) -> Value {
+    match light {
+        Light::PointLight(point_light) => json!({
+            "type": "PointLight",
+            "position": point_light.origin.coords.as_slice(),
+            "intensity": point_light.intensity,
+            "linear_attenuation": point_light.linear_attenuation,
+            "quadratic_attenuation": point_light.quadratic_attenuation,
+            "color": [point_light.color.r, point_light.color.g, point_light.color.b],
+        }),
+        Light::AmbientLight(ambient_light) => json!({
+            "type": "AmbientLight",
+            "intensity": ambient_light.intensity,
+            "color": [ambient_light.color.r, ambient_light.color.g, ambient_light.color.b],
+        }),
+    }
+}
+
+/*
+    Formats the JSON like the hand written scene files: indented by four spaces, with the
+    familiar fields such as type and id first, and short arrays and objects on a single line.
+*/
+pub fn to_json_string(json: &Value) -> String {
+    let mut output = String::new();
+    write_value(&mut output, json, 0, 0);
+    output.push('\n');
+    output
+}
+
+// Writes the value at the indent level, on a line already holding line_width characters
+fn write_value(output: &mut String, value: &Value, indent: usize, line_width: usize) {
+    let single_line = to_single_line(value);
+    let is_scalar_array = value
+        .as_array()
+        .is_some_and(|values| values.iter().all(is_scalar));
+    // Arrays of objects are always split, as they list objects, cameras or keyframes
+    let is_object_array = value
+        .as_array()
+        .is_some_and(|values| values.iter().any(Value::is_object));
+    let fits = line_width + single_line.len() <= MAX_LINE_WIDTH;
+    if !is_object_array && (is_scalar_array || fits) {
+        output.push_str(&single_line);
+        return;
+    }
+
+    let inner_indent = " ".repeat((indent + 1) * 4);
+    match value {
+        Value::Array(values) => {
+            output.push_str("[\n");
+            for (index, value) in values.iter().enumerate() {
+                output.push_str(&inner_indent);
+                write_value(output, value, indent + 1, inner_indent.len());
+                if index + 1 < values.len() {
+                    output.push(',');
+                }
+                output.push('\n');
+            }
+            output.push_str(&" ".repeat(indent * 4));
+            output.push(']');
+        }
+        Value::Object(fields) => {
+            output.push_str("{\n");
+            let fields = get_ordered_fields(fields);
+            for (index, (field, value)) in fields.iter().enumerate() {
+                let key = format!("{}{}: ", inner_indent, Value::String(field.to_string()));
+                output.push_str(&key);
+                write_value(output, value, indent + 1, key.len());
+                if index + 1 < fields.len() {
+                    output.push(',');
+                }
+                output.push('\n');
+            }
+            output.push_str(&" ".repeat(indent * 4));
+            output.push('}');
+        }
+        _ => output.push_str(&single_line),
+    }
+}
+
+fn to_single_line(value: &Value) -> String {
+    match value {
+        Value::Array(values) if !values.is_empty() => {
+            let values: Vec<String> = values.iter().map(to_single_line).collect();
+            format!("[{}]", values.join(", "))
+        }
+        Value::Object(fields) if !fields.is_empty() => {
+            let fields: Vec<String> = get_ordered_fields(fields)
+                .into_iter()
+                .map(|(field, value)| {
+                    format!(
+                        "{}: {}",
+                        Value::String(field.to_string()),
+                        to_single_line(value)
+                    )
+                })
+                .collect();
+            format!("{{ {} }}", fields.join(", "))
+        }
+        _ => value.to_string(),
+    }
+}
+
+fn is_scalar(value: &Value) -> bool {
+    !value.is_array() && !value.is_object()
+}
+
+fn get_ordered_fields(fields: &Map<String, Value>) -> Vec<(&str, &Value)> {
+    let get_rank = |field: &str| {
+        if let Some(rank) = LEADING_FIELDS.iter().position(|leading| *leading == field) {
+            rank
+        } else if let Some(rank) = TRAILING_FIELDS
+            .iter()
+            .position(|trailing| *trailing == field)
+        {
+            LEADING_FIELDS.len() + 1 + rank
+        } else {
+            LEADING_FIELDS.len()
+        }
+    };
+
+    // The map is sorted by field name, so fields of the same rank stay in alphabetical order
+    let mut fields: Vec<(&str, &Value)> = fields
+        .iter()
+        .map(|(field, value)| (field.as_str(), value))
+        .collect();
+    fields.sort_by_key(|(field, _)| get_rank(field));
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::EntityRegistry;
+    use crate::models::ModelStore;
+    use crate::scene_loader::{try_load_scene, try_load_scene_with_includes};
+    use include_dir::{include_dir, Dir};
+
+    static MODEL_DIR: Dir = include_dir!("$CARGO_MANIFEST_DIR/models");
+    static SCENE_DIR: Dir = include_dir!("$CARGO_MANIFEST_DIR/scenes");
+
+    fn read_scene_file(file_name: &str) -> Result<String, String> {
+        SCENE_DIR
+            .get_file(file_name)
+            .and_then(|file| file.contents_utf8())
+            .map(|contents| contents.to_string())
+            .ok_or_else(|| format!("Scene file {} not found", file_name))
+    }
+
+    // Whether the values are equal, allowing for rounding in numbers such as renormalized
+    // rotations
+    fn is_close(a: &Value, b: &Value) -> bool {
+        match (a, b) {
+            (Value::Number(a), Value::Number(b)) => {
+                (a.as_f64().unwrap() - b.as_f64().unwrap()).abs() < 1e-9
+            }
+            (Value::Array(a), Value::Array(b)) => {
+                a.len() == b.len() && a.iter().zip(b).all(|(a, b)| is_close(a, b))
+            }
+            (Value::Object(a), Value::Object(b)) => {
+                a.len() == b.len()
+                    && a.iter()
+                        .all(|(field, a)| b.get(field).is_some_and(|b| is_close(a, b)))
+            }
+            _ => a == b,
+        }
+    }
+
+    #[test]
+    fn exported_scenes_load_back_the_same() {
+        let mut model_store = ModelStore::new(&MODEL_DIR);
+        model_store.init();
+
+        for file in SCENE_DIR.files() {
+            let file_name = file.path().to_str().unwrap();
+            let scene = try_load_scene_with_includes(
+                file.contents_utf8().unwrap(),
+                &model_store,
+                &EntityRegistry::default(),
+                &read_scene_file,
+            )
+            .unwrap_or_else(|error| panic!("{}: {}", file_name, error));
+            let json = scene_to_json(&scene).unwrap();
+
+            let reloaded_scene = try_load_scene(&to_json_string(&json), &model_store)
+                .unwrap_or_else(|error| panic!("{} exported: {}", file_name, error));
+            assert_eq!(
+                reloaded_scene.scene_graph.len(),
+                scene.scene_graph.len(),
+                "{}",
+                file_name
+            );
+            assert_eq!(
+                reloaded_scene.lights.len(),
+                scene.lights.len(),
+                "{}",
+                file_name
+            );
+            assert_eq!(
+                reloaded_scene.cameras.len(),
+                scene.cameras.len(),
+                "{}",
+                file_name
+            );
+            assert!(
+                is_close(&scene_to_json(&reloaded_scene).unwrap(), &json),
+                "{} doesn't export the same after reloading",
+                file_name
+            );
+        }
+    }
+
+    #[test]
+    fn replacing_cameras_reports_migrated_files() {
+        let (json_string, migrated_from) = replace_cameras(r#"{ "objects": [] }"#, &[]).unwrap();
+        assert_eq!(migrated_from, Some(1));
+        let json: Value = serde_json::from_str(&json_string).unwrap();
+        assert_eq!(json["version"], json!(SCENE_VERSION));
+
+        let current = format!(r#"{{ "version": {}, "objects": [] }}"#, SCENE_VERSION);
+        let (_, migrated_from) = replace_cameras(&current, &[]).unwrap();
+        assert_eq!(migrated_from, None);
+    }
+}
@@ -0,0 +1,348 @@
+use nalgebra::{Unit, UnitQuaternion, Vector3};
+use serde_json::{json, Map, Value};
+
+use super::{join_path, SceneError};
+
+// Version of the scene file format written by this version of the renderer
+pub const SCENE_VERSION: u64 = 2;
+
+// Fields of the version 1 format kept by the migration, other fields were ignored by it
+const V1_WORLD_FIELDS: [&str; 4] = ["objects", "lights", "camera_path", "follow_cameras"];
+const V1_OBJECT_FIELDS: [&str; 6] = [
+    "type",
+    "id",
+    "animation",
+    "behaviors",
+    "material",
+    "children",
+];
+
+/*
+    Upgrades a scene file to the current version of the format. Files without a version field
+    are version 1, which had no common transform fields: positions were named origin, Square
+    and Rectangle rotated by rotation_axis and rotation_angle, while SpinningObject rotated by
+    Euler angles and spun about rotation_axis, and Rectangle was sized by width and height.
+*/
+pub fn migrate(json: Value) -> Result<Value, SceneError> {
+    let version = match json.get("version") {
+        None => 1,
+        Some(version) => version
+            .as_u64()
+            .filter(|version| *version >= 1)
+            .ok_or_else(|| SceneError::new("version", "Version must be a positive integer"))?,
+    };
+    if version > SCENE_VERSION {
+        return Err(SceneError::new(
+            "version",
+            &format!(
+                "Unsupported scene version {}, the latest is {}",
+                version, SCENE_VERSION
+            ),
+        ));
+    }
+
+    if version == 1 {
+        return migrate_v1(&json);
+    }
+    Ok(json)
+}
+
+fn migrate_v1(json: &Value) -> Result<Value, SceneError> {
+    let world = json
+        .as_object()
+        .ok_or_else(|| SceneError::new("", "Scene must be a JSON object"))?;
+
+    let mut migrated = Map::new();
+    migrated.insert("version".to_string(), json!(2));
+    for (field, value) in world {
+        if !V1_WORLD_FIELDS.contains(&field.as_str()) {
+            continue;
+        }
+        let value = match field.as_str() {
+            "objects" => Value::Array(migrate_v1_objects(value, "objects")?),
+            "lights" => Value::Array(migrate_v1_lights(value)?),
+            _ => value.clone(),
+        };
+        migrated.insert(field.clone(), value);
+    }
+    Ok(Value::Object(migrated))
+}
+
+fn migrate_v1_objects(objects: &Value, path: &str) -> Result<Vec<Value>, SceneError> {
+    objects
+        .as_array()
+        .ok_or_else(|| SceneError::new(path, "Objects must be an array"))?
+        .iter()
+        .enumerate()
+        .map(|(index, object)| migrate_v1_object(object, &format!("{}[{}]", path, index)))
+        .collect()
+}
+
+fn migrate_v1_object(object: &Value, path: &str) -> Result<Value, SceneError> {
+    let fields = object
+        .as_object()
+        .ok_or_else(|| SceneError::new(path, "Object must be a JSON object"))?;
+    let get_vector = |field: &str| -> Result<Option<Vector3<f64>>, SceneError> {
+        fields
+            .get(field)
+            .map(|value| {
+                serde_json::from_value::<[f64; 3]>(value.clone())
+                    .map(Vector3::from)
+                    .map_err(|error| SceneError::new(&join_path(path, field), &error.to_string()))
+            })
+            .transpose()
+    };
+    let get_number = |field: &str| -> Result<Option<f64>, SceneError> {
+        fields
+            .get(field)
+            .map(|value| {
+                value
+                    .as_f64()
+                    .ok_or_else(|| SceneError::new(&join_path(path, field), "Expected a number"))
+            })
+            .transpose()
+    };
+
+    let mut migrated = Map::new();
+    for field in V1_OBJECT_FIELDS {
+        if let Some(value) = fields.get(field) {
+            migrated.insert(field.to_string(), value.clone());
+        }
+    }
+    if let Some(children) = fields.get("children") {
+        let children = migrate_v1_objects(children, &join_path(path, "children"))?;
+        migrated.insert("children".to_string(), Value::Array(children));
+    }
+    if let Some(origin) = fields.get("origin") {
+        migrated.insert("position".to_string(), origin.clone());
+    }
+
+    let type_name = fields.get("type").and_then(Value::as_str).unwrap_or("");
+    match type_name {
+        "Square" | "Rectangle" => {
+            if let (Some(axis), Some(angle)) =
+                (get_vector("rotation_axis")?, get_number("rotation_angle")?)
+            {
+                migrated.insert(
+                    "rotation".to_string(),
+                    json!({ "axis_angle": { "axis": axis.as_slice(), "angle": angle } }),
+                );
+            }
+            if type_name == "Rectangle" {
+                let width = get_number("width")?.unwrap_or(1.0);
+                let height = get_number("height")?.unwrap_or(1.0);
+                migrated.insert("scale".to_string(), json!([width, height, 1.0]));
+                copy_fields(fields, &mut migrated, &["model", "color"]);
+            } else {
+                copy_fields(fields, &mut migrated, &["model", "scale"]);
+            }
+        }
+        "SpinningObject" | "Group" => {
+            // The initial spin angle is folded into the rotation, so the spin can start at zero
+            let euler = get_vector("rotation")?.unwrap_or_else(Vector3::zeros);
+            let spin_axis = get_vector("rotation_axis")?.unwrap_or_else(Vector3::y);
+            let spin_angle = get_number("rotation_angle")?.unwrap_or(0.0);
+            if spin_angle != 0.0 {
+                let spin_axis = Unit::try_new(spin_axis, 0.0).ok_or_else(|| {
+                    SceneError::new(
+                        &join_path(path, "rotation_axis"),
+                        "Rotation axis must not be zero",
+                    )
+                })?;
+                let rotation = UnitQuaternion::from_axis_angle(&spin_axis, spin_angle)
+                    * UnitQuaternion::from_euler_angles(euler.x, euler.y, euler.z);
+                let coords = rotation.coords;
+                migrated.insert(
+                    "rotation".to_string(),
+                    json!({ "quaternion": [coords.x, coords.y, coords.z, coords.w] }),
+                );
+            } else if fields.contains_key("rotation") {
+                migrated.insert("rotation".to_string(), json!({ "euler": euler.as_slice() }));
+            }
+            if fields.contains_key("rotation_axis") {
+                migrated.insert("spin_axis".to_string(), json!(spin_axis.as_slice()));
+            }
+            copy_fields(
+                fields,
+                &mut migrated,
+                &["model", "angular_velocity", "scale"],
+            );
+        }
+        _ => {
+            // Custom entities keep their own fields, with the common transform fields renamed
+            for (field, value) in fields {
+                if !migrated.contains_key(field) && field != "origin" {
+                    migrated.insert(field.clone(), value.clone());
+                }
+            }
+            if let Some(euler) = get_vector("rotation")? {
+                migrated.insert("rotation".to_string(), json!({ "euler": euler.as_slice() }));
+            }
+        }
+    }
+
+    Ok(Value::Object(migrated))
+}
+
+fn copy_fields(from: &Map<String, Value>, to: &mut Map<String, Value>, fields: &[&str]) {
+    for field in fields {
+        if let Some(value) = from.get(*field) {
+            to.insert(field.to_string(), value.clone());
+        }
+    }
+}
+
+fn migrate_v1_lights(lights: &Value) -> Result<Vec<Value>, SceneError> {
+    let lights = lights
+        .as_array()
+        .ok_or_else(|| SceneError::new("lights", "Lights must be an array"))?;
+
+    Ok(lights
+        .iter()
+        .map(|light| match light.as_object() {
+            Some(fields) => Value::Object(
+                fields
+                    .iter()
+                    .map(|(field, value)| match field.as_str() {
+                        "origin" => ("position".to_string(), value.clone()),
+                        _ => (field.clone(), value.clone()),
+                    })
+                    .collect(),
+            ),
+            None => light.clone(),
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+
+    #[test]
+    fn migrates_version_1_scenes() {
+        let v1 = json!({
+            "camera": { "position": [0.0, 0.0, 3.0] },
+            "camera_path": { "keyframes": [] },
+            "objects": [
+                {
+                    "type": "Square",
+                    "id": "floor",
+                    "model": "cube.json",
+                    "origin": [0.0, -1.0, 0.0],
+                    "rotation_axis": [1.0, 0.0, 0.0],
+                    "rotation_angle": 0.5,
+                    "scale": 2.0
+                },
+                {
+                    "type": "Rectangle",
+                    "model": "cube.json",
+                    "color": [255, 0, 0],
+                    "width": 2.0,
+                    "height": 3.0
+                },
+                {
+                    "type": "SpinningObject",
+                    "model": "cube.json",
+                    "rotation_axis": [0.0, 1.0, 0.0],
+                    "rotation_angle": PI / 2.0,
+                    "angular_velocity": 1.0,
+                    "children": [
+                        { "type": "Group", "rotation": [0.0, 0.5, 0.0], "angular_velocity": 2.0 }
+                    ]
+                },
+                { "type": "Custom", "origin": [1.0, 2.0, 3.0], "rotation": [0.1, 0.2, 0.3], "size": 4 }
+            ],
+            "lights": [
+                { "type": "PointLight", "origin": [0.0, 2.0, 0.0], "intensity": 1.0 }
+            ]
+        });
+        let v2 = json!({
+            "version": 2,
+            "camera_path": { "keyframes": [] },
+            "objects": [
+                {
+                    "type": "Square",
+                    "id": "floor",
+                    "model": "cube.json",
+                    "position": [0.0, -1.0, 0.0],
+                    "rotation": { "axis_angle": { "axis": [1.0, 0.0, 0.0], "angle": 0.5 } },
+                    "scale": 2.0
+                },
+                {
+                    "type": "Rectangle",
+                    "model": "cube.json",
+                    "color": [255, 0, 0],
+                    "scale": [2.0, 3.0, 1.0]
+                },
+                {
+                    "type": "SpinningObject",
+                    "model": "cube.json",
+                    // The initial spin angle is folded into the rotation
+                    "rotation": { "quaternion": [0.0, (PI / 4.0).sin(), 0.0, (PI / 4.0).cos()] },
+                    "spin_axis": [0.0, 1.0, 0.0],
+                    "angular_velocity": 1.0,
+                    "children": [
+                        {
+                            "type": "Group",
+                            "rotation": { "euler": [0.0, 0.5, 0.0] },
+                            "angular_velocity": 2.0
+                        }
+                    ]
+                },
+                {
+                    "type": "Custom",
+                    "position": [1.0, 2.0, 3.0],
+                    "rotation": { "euler": [0.1, 0.2, 0.3] },
+                    "size": 4
+                }
+            ],
+            "lights": [
+                { "type": "PointLight", "position": [0.0, 2.0, 0.0], "intensity": 1.0 }
+            ]
+        });
+
+        assert_eq!(migrate(v1).unwrap(), v2);
+        // Explicitly versioned files migrate the same way
+        let v1 = json!({ "version": 1, "objects": [{ "type": "Square", "origin": [1, 2, 3] }] });
+        assert_eq!(
+            migrate(v1).unwrap(),
+            json!({ "version": 2, "objects": [{ "type": "Square", "position": [1, 2, 3] }] })
+        );
+    }
+
+    #[test]
+    fn keeps_current_version_scenes() {
+        let v2 = json!({ "version": 2, "objects": [{ "type": "Square", "origin": [0, 0, 0] }] });
+        assert_eq!(migrate(v2.clone()).unwrap(), v2);
+    }
+
+    #[test]
+    fn rejects_unsupported_versions() {
+        let error = migrate(json!({ "version": SCENE_VERSION + 1 })).unwrap_err();
+        assert_eq!(error.path, "version");
+        assert_eq!(
+            error.message,
+            format!(
+                "Unsupported scene version {}, the latest is {}",
+                SCENE_VERSION + 1,
+                SCENE_VERSION
+            )
+        );
+
+        for version in [json!(0), json!(1.5), json!("2")] {
+            let error = migrate(json!({ "version": version })).unwrap_err();
+            assert_eq!(error.path, "version");
+            assert_eq!(error.message, "Version must be a positive integer");
+        }
+    }
+
+    #[test]
+    fn rejects_zero_spin_axes_with_a_spin_angle() {
+        let v1 = json!({
+            "objects": [{ "type": "SpinningObject", "rotation_axis": [0, 0, 0], "rotation_angle": 1.0 }]
+        });
+        let error = migrate(v1).unwrap_err();
+        assert_eq!(error.path, "objects[0].rotation_axis");
+    }
+}
@@ -1,11 +1,17 @@
+use std::collections::HashSet;
 use std::io;
 use std::io::Write;
 use std::time;
 
 use crossterm::{
     cursor::{Hide, MoveTo, Show},
+    event::{
+        self, DisableFocusChange, DisableMouseCapture, EnableFocusChange, EnableMouseCapture,
+        Event, KeyCode, KeyboardEnhancementFlags, PopKeyboardEnhancementFlags,
+        PushKeyboardEnhancementFlags,
+    },
     queue,
-    style::{Color, Print, SetBackgroundColor},
+    style::{Color, Print, SetBackgroundColor, SetForegroundColor},
     terminal,
     terminal::{
         disable_raw_mode, enable_raw_mode, Clear, ClearType, EnterAlternateScreen,
@@ -16,9 +22,15 @@ use crossterm::{
 
 use crate::buffer::Buffer;
 use crate::geometry::ProjectionResult;
-use crate::terminal::keyboard::{Keyboard, Keys};
+use crate::terminal::input_event::InputEvent;
+use crate::terminal::key_bindings::{normalize_key_code, Action, InputConfig};
+use crate::terminal::keyboard::Keyboard;
+use crate::terminal::mouse::{Mouse, MouseState};
 
+pub mod input_event;
+pub mod key_bindings;
 pub mod keyboard;
+pub mod mouse;
 
 pub struct Terminal {
     stdout: io::Stdout,
@@ -27,8 +39,16 @@ pub struct Terminal {
     default_color: [u8; 3],
     use_true_color: bool,
     aspect_ratio: f64,
+    fill_terminal: bool,
+    pixel_aspect_ratio: f64,
+    was_resized: bool,
     screen_buffer: Option<Buffer<[u8; 3]>>,
     keyboard: Keyboard,
+    mouse: Mouse,
+    input_config: InputConfig,
+    is_keyboard_enhanced: bool,
+    input_events: Vec<InputEvent>,
+    hud_message: Option<String>,
 }
 
 impl Terminal {
@@ -40,36 +60,176 @@ impl Terminal {
             default_color,
             use_true_color,
             aspect_ratio,
+            fill_terminal: false,
+            pixel_aspect_ratio: 1.0,
+            was_resized: false,
             screen_buffer: None,
             keyboard: Keyboard::new(),
+            mouse: Mouse::new(),
+            input_config: InputConfig::default(),
+            is_keyboard_enhanced: false,
+            input_events: Vec::new(),
+            hud_message: None,
         }
     }
 
+    // When enabled the screen buffer fills the whole terminal, instead of being
+    // letterboxed to the fixed aspect ratio
+    pub fn set_fill_terminal(&mut self, fill_terminal: bool) {
+        self.fill_terminal = fill_terminal;
+    }
+
+    pub fn set_input_config(&mut self, input_config: InputConfig) {
+        self.keyboard
+            .set_hold_timeout(time::Duration::from_secs_f64(input_config.key_hold_timeout));
+        self.input_config = input_config;
+    }
+
     pub fn init(&mut self) -> io::Result<()> {
         enable_raw_mode()?;
 
         self.stdout.execute(EnterAlternateScreen)?;
+        self.stdout.execute(EnableMouseCapture)?;
+        self.stdout.execute(EnableFocusChange)?;
+
+        // Key release events are only reported by terminals supporting the kitty keyboard
+        // protocol, otherwise the keyboard falls back to expiring held keys
+        self.is_keyboard_enhanced = terminal::supports_keyboard_enhancement().unwrap_or(false);
+        if self.is_keyboard_enhanced {
+            self.stdout.execute(PushKeyboardEnhancementFlags(
+                KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES
+                    | KeyboardEnhancementFlags::REPORT_EVENT_TYPES
+                    | KeyboardEnhancementFlags::REPORT_ALL_KEYS_AS_ESCAPE_CODES,
+            ))?;
+        }
+        self.keyboard
+            .set_supports_release_events(self.is_keyboard_enhanced);
+
         queue!(self.stdout, Hide)?;
         Ok(())
     }
 
     pub fn update(&mut self) -> io::Result<()> {
-        self.keyboard.update()?;
+        self.keyboard.update();
+        self.mouse.clear();
+        self.input_events.clear();
+
+        // Process and clear the crossterm event buffer
+        const MAX_POLL_COUNT: usize = 1000;
+        for _ in 0..MAX_POLL_COUNT {
+            if !event::poll(time::Duration::ZERO)? {
+                break;
+            }
+            let event = event::read()?;
+            match event {
+                Event::Key(key_event) => self.keyboard.process_key_event(key_event),
+                Event::Mouse(mouse_event) => self.mouse.process_mouse_event(mouse_event),
+                // Releases can't be seen while unfocused, so avoid keys getting stuck down
+                Event::FocusLost => self.keyboard.release_all(),
+                _ => {}
+            }
+            self.input_events.extend(InputEvent::from_event(&event));
+        }
+
+        self.update_dimensions()?;
         Ok(())
     }
 
-    pub fn get_key_presses(&self) -> Vec<Keys> {
-        Vec::from_iter(self.keyboard.pressed_keys.clone())
+    pub fn get_actions(&self) -> Vec<Action> {
+        // Several keys may be bound to the same action, which should only apply once
+        let actions: HashSet<Action> = self
+            .keyboard
+            .get_held_keys()
+            .filter_map(|key_code| self.input_config.key_bindings.get_action(key_code))
+            .collect();
+        Vec::from_iter(actions)
+    }
+
+    // Actions whose key was newly pressed during the last update, for one-shot actions
+    // such as toggles
+    pub fn get_triggered_actions(&self) -> Vec<Action> {
+        let actions: HashSet<Action> = self
+            .keyboard
+            .get_pressed_keys()
+            .filter_map(|key_code| self.input_config.key_bindings.get_action(key_code))
+            .collect();
+        Vec::from_iter(actions)
+    }
+
+    pub fn is_key_pressed(&self, key_code: KeyCode) -> bool {
+        self.keyboard
+            .get_pressed_keys()
+            .any(|pressed_key_code| *pressed_key_code == normalize_key_code(key_code))
+    }
+
+    pub fn get_input_events(&self) -> &[InputEvent] {
+        &self.input_events
+    }
+
+    pub fn set_use_true_color(&mut self, use_true_color: bool) {
+        self.use_true_color = use_true_color;
+    }
+
+    pub fn get_mouse_state(&self) -> MouseState {
+        self.mouse.state
+    }
+
+    // The screen buffer pixel drawn at the terminal cell, such as the one under the mouse.
+    // Rows are drawn one per terminal row after the top margin, with pixels two columns wide
+    pub fn get_pixel_at(&self, column: u16, row: u16) -> Option<(usize, usize)> {
+        let x = (column as usize).checked_sub(1)? / 2;
+        let y = row as usize;
+        (row >= 1 && x < self.width && y < self.height).then_some((x, y))
     }
 
     pub fn is_ctrl_c_pressed(&self) -> bool {
-        self.keyboard.pressed_keys.contains(&Keys::CtrlC)
+        self.keyboard.is_ctrl_c_pressed()
+    }
+
+    // True if the screen buffer dimensions changed during the last update
+    pub fn was_resized(&self) -> bool {
+        self.was_resized
+    }
+
+    // Shows the message on the line below the screen buffer until it is cleared with None
+    pub fn set_hud_message(&mut self, hud_message: Option<String>) {
+        self.hud_message = hud_message;
+    }
+
+    // Width over height of the screen buffer as displayed, accounting for the shape of
+    // the terminal cells
+    pub fn get_aspect_ratio(&self) -> f64 {
+        if self.height == 0 {
+            return self.aspect_ratio;
+        }
+        (self.width as f64) * self.pixel_aspect_ratio / (self.height as f64)
     }
 
     pub fn get_mutable_screen_buffer_reference(&mut self) -> &mut Buffer<[u8; 3]> {
-        let (new_width, new_height) = get_aspect_corrected_dimensions(self.aspect_ratio);
-        if new_width != self.width || new_height != self.height || self.screen_buffer.is_none() {
-            clear_screen(&mut self.stdout).ok();
+        if self.screen_buffer.is_none() {
+            self.update_dimensions().ok();
+        }
+
+        self.screen_buffer.as_mut().unwrap()
+    }
+
+    fn update_dimensions(&mut self) -> io::Result<()> {
+        let (columns, rows) = terminal::size()?;
+        self.pixel_aspect_ratio = get_pixel_aspect_ratio();
+
+        let (new_width, new_height) = if self.fill_terminal {
+            get_full_dimensions(columns, rows)
+        } else {
+            get_aspect_corrected_dimensions(
+                columns,
+                rows,
+                self.aspect_ratio / self.pixel_aspect_ratio,
+            )
+        };
+
+        self.was_resized = new_width != self.width || new_height != self.height;
+        if self.was_resized || self.screen_buffer.is_none() {
+            clear_screen(&mut self.stdout)?;
             self.width = new_width;
             self.height = new_height;
             self.screen_buffer = Some(Buffer::<[u8; 3]>::new(
@@ -78,8 +238,7 @@ impl Terminal {
                 self.height,
             ));
         }
-
-        self.screen_buffer.as_mut().unwrap()
+        Ok(())
     }
 
     pub fn output_screen_buffer(&mut self) -> io::Result<()> {
@@ -101,12 +260,34 @@ impl Terminal {
             }
             queue!(self.stdout, MoveTo(1, (y + 1) as u16))?;
         }
+
+        // The message is padded to the screen width to overwrite any previous message
+        let hud_width = 2 * self.width;
+        let hud_message: String = self
+            .hud_message
+            .as_deref()
+            .unwrap_or("")
+            .chars()
+            .take(hud_width)
+            .collect();
+        queue!(
+            self.stdout,
+            MoveTo(1, self.height as u16),
+            SetBackgroundColor(Color::Black),
+            SetForegroundColor(Color::White),
+            Print(format!("{:<width$}", hud_message, width = hud_width))
+        )?;
         flush(&mut self.stdout)?;
         Ok(())
     }
 
     pub fn destroy(&mut self) -> io::Result<()> {
         queue!(self.stdout, Show)?;
+        if self.is_keyboard_enhanced {
+            self.stdout.execute(PopKeyboardEnhancementFlags)?;
+        }
+        self.stdout.execute(DisableFocusChange)?;
+        self.stdout.execute(DisableMouseCapture)?;
         disable_raw_mode()?;
         self.stdout.execute(LeaveAlternateScreen)?;
         clear_screen(&mut self.stdout)?;
@@ -154,10 +335,19 @@ fn clear_screen(stdout: &mut std::io::Stdout) -> io::Result<()> {
     Ok(())
 }
 
-fn get_aspect_corrected_dimensions(target_aspect_rato: f64) -> (usize, usize) {
-    let (columns, rows) = terminal::size().expect("Failed to get terminal size");
-    let width = (columns / 2 - 2) as usize;
-    let height = (rows - 2) as usize;
+fn get_full_dimensions(columns: u16, rows: u16) -> (usize, usize) {
+    // Each pixel is two columns wide, and a margin is left around the screen
+    let width = (columns / 2).saturating_sub(2).max(1) as usize;
+    let height = rows.saturating_sub(2).max(1) as usize;
+    (width, height)
+}
+
+fn get_aspect_corrected_dimensions(
+    columns: u16,
+    rows: u16,
+    target_aspect_rato: f64,
+) -> (usize, usize) {
+    let (width, height) = get_full_dimensions(columns, rows);
 
     let aspect: f64 = (width as f64) / (height as f64);
     if aspect > target_aspect_rato {
@@ -166,6 +356,19 @@ fn get_aspect_corrected_dimensions(target_aspect_rato: f64) -> (usize, usize) {
     (width, ((width as f64) / target_aspect_rato) as usize)
 }
 
+// Width over height of a two column pixel. Terminals that don't report their size in
+// pixels are assumed to have cells twice as tall as they are wide
+fn get_pixel_aspect_ratio() -> f64 {
+    match terminal::window_size() {
+        Ok(window_size) if window_size.width > 0 && window_size.height > 0 => {
+            let cell_width = (window_size.width as f64) / (window_size.columns as f64);
+            let cell_height = (window_size.height as f64) / (window_size.rows as f64);
+            2.0 * cell_width / cell_height
+        }
+        _ => 1.0,
+    }
+}
+
 #[allow(dead_code)]
 pub fn print_debug_info(
     stdout: &mut std::io::Stdout,
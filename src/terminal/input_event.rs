@@ -0,0 +1,52 @@
+use crossterm::event::{Event, KeyCode, KeyEventKind, KeyModifiers, MouseEvent};
+
+use crate::terminal::key_bindings::normalize_key_code;
+
+/// A single input event read from the terminal during the last update. Applications can
+/// inspect these to react to any key, modifier combination, mouse event or resize, on top
+/// of the bound actions.
+#[derive(Clone, Debug, PartialEq)]
+pub enum InputEvent {
+    Key {
+        key_code: KeyCode,
+        modifiers: KeyModifiers,
+        kind: KeyEventKind,
+    },
+    Mouse(MouseEvent),
+    Resize {
+        columns: u16,
+        rows: u16,
+    },
+    FocusGained,
+    FocusLost,
+}
+
+impl InputEvent {
+    pub fn from_event(event: &Event) -> Option<Self> {
+        match event {
+            Event::Key(key_event) => Some(InputEvent::Key {
+                key_code: key_event.code,
+                modifiers: key_event.modifiers,
+                kind: key_event.kind,
+            }),
+            Event::Mouse(mouse_event) => Some(InputEvent::Mouse(*mouse_event)),
+            Event::Resize(columns, rows) => Some(InputEvent::Resize {
+                columns: *columns,
+                rows: *rows,
+            }),
+            Event::FocusGained => Some(InputEvent::FocusGained),
+            Event::FocusLost => Some(InputEvent::FocusLost),
+            Event::Paste(_) => None,
+        }
+    }
+
+    // Whether the event presses the key. Letters match in either case, like the key bindings,
+    // so Shift and Caps Lock don't change which key is pressed
+    pub fn is_key_press(&self, key_code: KeyCode) -> bool {
+        matches!(
+            self,
+            InputEvent::Key { key_code: code, kind: KeyEventKind::Press, .. }
+                if normalize_key_code(*code) == normalize_key_code(key_code)
+        )
+    }
+}
@@ -0,0 +1,216 @@
+use std::collections::HashMap;
+
+use crossterm::event::KeyCode;
+use serde::Deserialize;
+
+#[derive(Copy, Clone, Debug, Deserialize, Eq, Hash, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    MoveForward,
+    MoveBackward,
+    MoveLeft,
+    MoveRight,
+    MoveUp,
+    MoveDown,
+    YawLeft,
+    YawRight,
+    PitchUp,
+    PitchDown,
+    RollLeft,
+    RollRight,
+    Quit,
+    TogglePause,
+    NextScene,
+    NextCamera,
+    NextViewPreset,
+    ToggleWireframe,
+    ToggleWalkMode,
+    ToggleColorMode,
+    SaveCamera,
+    SaveScene,
+    ToggleEditor,
+    EditorNextSelection,
+    EditorPreviousSelection,
+    EditorNextTool,
+    EditorAxisX,
+    EditorAxisY,
+    EditorAxisZ,
+    EditorIncrease,
+    EditorDecrease,
+    EditorNextModel,
+    EditorPreviousModel,
+    EditorAddObject,
+    EditorRemoveSelection,
+}
+
+/// Maps held keys to the actions they trigger. A key triggers at most one action,
+/// while an action can be bound to any number of keys.
+#[derive(Clone, Debug)]
+pub struct KeyBindings {
+    bindings: HashMap<KeyCode, Action>,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        let mut key_bindings = Self {
+            bindings: HashMap::new(),
+        };
+        key_bindings.bind(KeyCode::Char('w'), Action::MoveForward);
+        key_bindings.bind(KeyCode::Char('s'), Action::MoveBackward);
+        key_bindings.bind(KeyCode::Char('a'), Action::MoveLeft);
+        key_bindings.bind(KeyCode::Char('d'), Action::MoveRight);
+        key_bindings.bind(KeyCode::Char(' '), Action::MoveUp);
+        key_bindings.bind(KeyCode::Char('c'), Action::MoveDown);
+        key_bindings.bind(KeyCode::Left, Action::YawLeft);
+        key_bindings.bind(KeyCode::Right, Action::YawRight);
+        key_bindings.bind(KeyCode::Up, Action::PitchUp);
+        key_bindings.bind(KeyCode::Down, Action::PitchDown);
+        key_bindings.bind(KeyCode::Char('z'), Action::RollLeft);
+        key_bindings.bind(KeyCode::Char('x'), Action::RollRight);
+        key_bindings.bind(KeyCode::Char('q'), Action::Quit);
+        key_bindings.bind(KeyCode::Esc, Action::Quit);
+        key_bindings.bind(KeyCode::Char('p'), Action::TogglePause);
+        key_bindings.bind(KeyCode::Char('n'), Action::NextScene);
+        key_bindings.bind(KeyCode::Char('v'), Action::NextCamera);
+        key_bindings.bind(KeyCode::Char('o'), Action::NextViewPreset);
+        key_bindings.bind(KeyCode::Char('f'), Action::ToggleWireframe);
+        key_bindings.bind(KeyCode::Char('g'), Action::ToggleWalkMode);
+        key_bindings.bind(KeyCode::Char('t'), Action::ToggleColorMode);
+        key_bindings.bind(KeyCode::Char('k'), Action::SaveCamera);
+        key_bindings.bind(KeyCode::Char('l'), Action::SaveScene);
+        key_bindings.bind(KeyCode::Char('e'), Action::ToggleEditor);
+        key_bindings.bind(KeyCode::Char(']'), Action::EditorNextSelection);
+        key_bindings.bind(KeyCode::Char('['), Action::EditorPreviousSelection);
+        key_bindings.bind(KeyCode::Char('m'), Action::EditorNextTool);
+        key_bindings.bind(KeyCode::Char('1'), Action::EditorAxisX);
+        key_bindings.bind(KeyCode::Char('2'), Action::EditorAxisY);
+        key_bindings.bind(KeyCode::Char('3'), Action::EditorAxisZ);
+        key_bindings.bind(KeyCode::Char('='), Action::EditorIncrease);
+        key_bindings.bind(KeyCode::Char('-'), Action::EditorDecrease);
+        key_bindings.bind(KeyCode::Char('.'), Action::EditorNextModel);
+        key_bindings.bind(KeyCode::Char(','), Action::EditorPreviousModel);
+        key_bindings.bind(KeyCode::Char('i'), Action::EditorAddObject);
+        key_bindings.bind(KeyCode::Delete, Action::EditorRemoveSelection);
+        key_bindings.bind(KeyCode::Backspace, Action::EditorRemoveSelection);
+        key_bindings
+    }
+}
+
+impl KeyBindings {
+    pub fn bind(&mut self, key_code: KeyCode, action: Action) {
+        self.bindings.insert(key_code, action);
+    }
+
+    // Removes every key bound to the action
+    pub fn unbind(&mut self, action: Action) {
+        self.bindings
+            .retain(|_, bound_action| *bound_action != action);
+    }
+
+    pub fn get_action(&self, key_code: &KeyCode) -> Option<Action> {
+        self.bindings.get(key_code).copied()
+    }
+}
+
+// Seconds a key counts as held after its last press, longer than the usual OS key repeat delay
+// so a held key isn't dropped between the first press and the first repeat
+pub const DEFAULT_KEY_HOLD_TIMEOUT: f64 = 0.5;
+
+#[derive(Deserialize, Debug)]
+struct JsonInputConfig {
+    key_hold_timeout: Option<f64>,
+    #[serde(default)]
+    bindings: HashMap<Action, Vec<String>>,
+}
+
+/// Input settings loaded from a config file, for example:
+/// ```json
+/// {
+///     "key_hold_timeout": 0.5,
+///     "bindings": {
+///         "move_forward": ["w", "up"],
+///         "move_down": ["c", "pagedown"]
+///     }
+/// }
+/// ```
+/// Actions missing from `bindings` keep their default keys.
+#[derive(Clone, Debug)]
+pub struct InputConfig {
+    // Seconds a key counts as held after its last press, used when the terminal
+    // can't report key release events
+    pub key_hold_timeout: f64,
+    pub key_bindings: KeyBindings,
+}
+
+impl Default for InputConfig {
+    fn default() -> Self {
+        Self {
+            key_hold_timeout: DEFAULT_KEY_HOLD_TIMEOUT,
+            key_bindings: KeyBindings::default(),
+        }
+    }
+}
+
+impl InputConfig {
+    pub fn from_json(json_string: &str) -> Result<Self, String> {
+        let json_input_config: JsonInputConfig = serde_json::from_str(json_string)
+            .map_err(|err| format!("Failed to deserialize input config: {}", err))?;
+
+        let mut input_config = Self::default();
+        if let Some(key_hold_timeout) = json_input_config.key_hold_timeout {
+            if !key_hold_timeout.is_finite() || key_hold_timeout < 0.0 {
+                return Err(format!(
+                    "key_hold_timeout must be a non-negative number of seconds, got {}",
+                    key_hold_timeout
+                ));
+            }
+            input_config.key_hold_timeout = key_hold_timeout;
+        }
+
+        for (action, key_names) in json_input_config.bindings {
+            input_config.key_bindings.unbind(action);
+            for key_name in key_names {
+                let key_code = parse_key_name(&key_name)
+                    .ok_or_else(|| format!("Unknown key name {}", key_name))?;
+                input_config.key_bindings.bind(key_code, action);
+            }
+        }
+
+        Ok(input_config)
+    }
+}
+
+// Converts a key to the form stored by the keyboard, so that for example 'W' and 'w'
+// refer to the same physical key
+pub fn normalize_key_code(key_code: KeyCode) -> KeyCode {
+    match key_code {
+        KeyCode::Char(c) => KeyCode::Char(c.to_ascii_lowercase()),
+        KeyCode::BackTab => KeyCode::Tab,
+        other => other,
+    }
+}
+
+pub fn parse_key_name(key_name: &str) -> Option<KeyCode> {
+    let lowercase_name = key_name.to_lowercase();
+    let key_code = match lowercase_name.as_str() {
+        "space" => KeyCode::Char(' '),
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "enter" => KeyCode::Enter,
+        "tab" => KeyCode::Tab,
+        "esc" | "escape" => KeyCode::Esc,
+        "backspace" => KeyCode::Backspace,
+        "delete" => KeyCode::Delete,
+        "insert" => KeyCode::Insert,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        "pageup" => KeyCode::PageUp,
+        "pagedown" => KeyCode::PageDown,
+        name if name.chars().count() == 1 => KeyCode::Char(name.chars().next()?),
+        name if name.starts_with('f') => KeyCode::F(name[1..].parse::<u8>().ok()?),
+        _ => return None,
+    };
+    Some(key_code)
+}
@@ -1,26 +1,23 @@
-use std::collections::HashSet;
-use std::io;
-use std::time::Duration;
-
-use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyModifiers};
-
-#[derive(Clone, Eq, Hash, PartialEq)]
-pub enum Keys {
-    W,
-    A,
-    S,
-    D,
-    C,
-    Space,
-    CtrlC,
-    Up,
-    Down,
-    Left,
-    Right,
-}
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+use crossterm::event::{KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
 
+use crate::terminal::key_bindings::{normalize_key_code, DEFAULT_KEY_HOLD_TIMEOUT};
+
+/*
+    Tracks which keys are currently held down. Terminals supporting the keyboard
+    enhancement protocol report key releases, so a key is held from its press until its
+    release. Other terminals only report presses and OS key repeats, so a key is instead
+    considered held until no press has been seen for the hold timeout, and every press,
+    including the repeats, counts as a new press so quick taps aren't lost.
+*/
 pub struct Keyboard {
-    pub pressed_keys: HashSet<Keys>,
+    held_keys: HashMap<KeyCode, Instant>,
+    pressed_keys: HashSet<KeyCode>,
+    is_ctrl_c_pressed: bool,
+    supports_release_events: bool,
+    hold_timeout: Duration,
 }
 
 impl Default for Keyboard {
@@ -32,61 +29,74 @@ impl Default for Keyboard {
 impl Keyboard {
     pub fn new() -> Self {
         Self {
+            held_keys: HashMap::new(),
             pressed_keys: HashSet::new(),
+            is_ctrl_c_pressed: false,
+            supports_release_events: false,
+            hold_timeout: Duration::from_secs_f64(DEFAULT_KEY_HOLD_TIMEOUT),
         }
     }
 
-    pub fn update(&mut self) -> io::Result<()> {
+    pub fn set_supports_release_events(&mut self, supports_release_events: bool) {
+        self.supports_release_events = supports_release_events;
+    }
+
+    pub fn set_hold_timeout(&mut self, hold_timeout: Duration) {
+        self.hold_timeout = hold_timeout;
+    }
+
+    // Expires keys whose hold timeout has elapsed. Called once per frame before new
+    // events are processed
+    pub fn update(&mut self) {
+        self.is_ctrl_c_pressed = false;
         self.pressed_keys.clear();
-        // Process and clear the crossterm event buffer
-        const MAX_POLL_COUNT: usize = 1000;
-        for _ in 0..MAX_POLL_COUNT {
-            if event::poll(Duration::ZERO)? {
-                if let Event::Key(key_event) = event::read()? {
-                    self.process_key_event(key_event);
-                }
-            }
+        if self.supports_release_events {
+            return;
         }
-        Ok(())
+
+        let hold_timeout = self.hold_timeout;
+        self.held_keys
+            .retain(|_, last_pressed| last_pressed.elapsed() < hold_timeout);
+    }
+
+    pub fn release_all(&mut self) {
+        self.held_keys.clear();
     }
 
-    fn process_key_event(&mut self, key_event: KeyEvent) {
-        match key_event.code {
-            KeyCode::Char('c') | KeyCode::Char('C') => {
-                if key_event.modifiers.contains(KeyModifiers::CONTROL) {
-                    self.pressed_keys.insert(Keys::CtrlC);
-                } else {
-                    self.pressed_keys.insert(Keys::C);
+    pub fn process_key_event(&mut self, key_event: KeyEvent) {
+        let key_code = normalize_key_code(key_event.code);
+
+        if key_code == KeyCode::Char('c') && key_event.modifiers.contains(KeyModifiers::CONTROL) {
+            self.is_ctrl_c_pressed = true;
+            return;
+        }
+
+        match key_event.kind {
+            KeyEventKind::Press | KeyEventKind::Repeat => {
+                // Without release events, OS key repeats also arrive as presses, and a second tap
+                // can't be told apart from a repeat
+                self.held_keys.insert(key_code, Instant::now());
+                if key_event.kind == KeyEventKind::Press {
+                    self.pressed_keys.insert(key_code);
                 }
             }
-            KeyCode::Char('w') | KeyCode::Char('W') => {
-                self.pressed_keys.insert(Keys::W);
+            KeyEventKind::Release => {
+                self.held_keys.remove(&key_code);
             }
-            KeyCode::Char('s') | KeyCode::Char('S') => {
-                self.pressed_keys.insert(Keys::S);
-            }
-            KeyCode::Char('d') | KeyCode::Char('D') => {
-                self.pressed_keys.insert(Keys::D);
-            }
-            KeyCode::Char('a') | KeyCode::Char('A') => {
-                self.pressed_keys.insert(Keys::A);
-            }
-            KeyCode::Char(' ') => {
-                self.pressed_keys.insert(Keys::Space);
-            }
-            KeyCode::Left => {
-                self.pressed_keys.insert(Keys::Left);
-            }
-            KeyCode::Right => {
-                self.pressed_keys.insert(Keys::Right);
-            }
-            KeyCode::Up => {
-                self.pressed_keys.insert(Keys::Up);
-            }
-            KeyCode::Down => {
-                self.pressed_keys.insert(Keys::Down);
-            }
-            _ => {}
         }
     }
+
+    pub fn get_held_keys(&self) -> impl Iterator<Item = &KeyCode> {
+        self.held_keys.keys()
+    }
+
+    // Keys that went down since the last update. Repeats are only excluded when the terminal
+    // reports them
+    pub fn get_pressed_keys(&self) -> impl Iterator<Item = &KeyCode> {
+        self.pressed_keys.iter()
+    }
+
+    pub fn is_ctrl_c_pressed(&self) -> bool {
+        self.is_ctrl_c_pressed
+    }
 }
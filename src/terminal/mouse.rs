@@ -0,0 +1,74 @@
+use crossterm::event::{MouseButton, MouseEvent, MouseEventKind};
+
+/// Mouse activity accumulated since the last terminal update. Deltas are measured in
+/// terminal cells, scroll in wheel notches (positive is away from the user).
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct MouseState {
+    pub position: Option<(u16, u16)>,
+    pub drag_delta: (f64, f64),
+    pub scroll_delta: f64,
+    pub is_left_button_down: bool,
+    pub is_right_button_down: bool,
+}
+
+pub struct Mouse {
+    pub state: MouseState,
+}
+
+impl Default for Mouse {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Mouse {
+    pub fn new() -> Self {
+        Self {
+            state: MouseState::default(),
+        }
+    }
+
+    // Clears the per frame deltas, while keeping the button and position state
+    pub fn clear(&mut self) {
+        self.state.drag_delta = (0.0, 0.0);
+        self.state.scroll_delta = 0.0;
+    }
+
+    pub fn process_mouse_event(&mut self, mouse_event: MouseEvent) {
+        let position = (mouse_event.column, mouse_event.row);
+        let delta = self
+            .state
+            .position
+            .map(|(x, y)| (position.0 as f64 - x as f64, position.1 as f64 - y as f64))
+            .unwrap_or((0.0, 0.0));
+
+        match mouse_event.kind {
+            MouseEventKind::Down(button) => self.set_button_state(button, true),
+            MouseEventKind::Up(button) => self.set_button_state(button, false),
+            MouseEventKind::Drag(button) => {
+                // Drag events can arrive without a preceding down event if the press
+                // started outside of the terminal window
+                self.set_button_state(button, true);
+                self.state.drag_delta.0 += delta.0;
+                self.state.drag_delta.1 += delta.1;
+            }
+            MouseEventKind::Moved => {
+                self.state.is_left_button_down = false;
+                self.state.is_right_button_down = false;
+            }
+            MouseEventKind::ScrollUp => self.state.scroll_delta += 1.0,
+            MouseEventKind::ScrollDown => self.state.scroll_delta -= 1.0,
+            _ => {}
+        }
+
+        self.state.position = Some(position);
+    }
+
+    fn set_button_state(&mut self, button: MouseButton, is_down: bool) {
+        match button {
+            MouseButton::Left => self.state.is_left_button_down = is_down,
+            MouseButton::Right => self.state.is_right_button_down = is_down,
+            MouseButton::Middle => {}
+        }
+    }
+}